@@ -0,0 +1,389 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+};
+
+use bevy::{input::InputSystem, prelude::*};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+/// Where rebound controls are saved, relative to the working directory.
+const BINDINGS_PATH: &str = "input.ron";
+/// How far a stick has to be pushed to count as pressed.
+const AXIS_THRESHOLD: f32 = 0.5;
+/// Actions that are never read at the same time and can share bindings,
+/// `Confirm` drives menus and `Interact` the overworld. Their defaults share
+/// the South button, rebinding and the duplicate check leave that alone.
+const SHARED: [(Action, Action); 1] = [(Action::Confirm, Action::Interact)];
+
+/// What the player wants to do, independent of the keys and buttons bound
+/// to it.
+#[derive(
+    Deserialize,
+    Serialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Sprint,
+    Confirm,
+    Cancel,
+    Interact,
+    Equipment,
+    Minimap,
+    Controls,
+    VolumeUp,
+    VolumeDown,
+    Quit,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum Binding {
+    Key(KeyCode),
+    /// Keys held together, e.g. `Chord([LControl, Q])`.
+    Chord(Vec<KeyCode>),
+    /// Button of any connected gamepad.
+    Button(GamepadButtonType),
+    /// Stick of any connected gamepad pushed past the threshold.
+    Axis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+/// Keys and buttons bound to every action, saved whenever they change.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Bindings(pub BTreeMap<Action, Vec<Binding>>);
+
+/// Actions held down this frame, updated before any other system runs.
+#[derive(Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+pub struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActionState>()
+            .add_startup_system_to_stage(
+                StartupStage::PreStartup,
+                load_bindings,
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_actions.after(InputSystem),
+            );
+    }
+}
+
+impl Action {
+    pub const ALL: [Action; 14] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Sprint,
+        Action::Confirm,
+        Action::Cancel,
+        Action::Interact,
+        Action::Equipment,
+        Action::Minimap,
+        Action::Controls,
+        Action::VolumeUp,
+        Action::VolumeDown,
+        Action::Quit,
+    ];
+
+    fn default_bindings(self) -> Vec<Binding> {
+        use GamepadAxisType::*;
+        use GamepadButtonType::*;
+        let key = Binding::Key;
+        let button = Binding::Button;
+        let stick = |axis, positive| Binding::Axis { axis, positive };
+
+        match self {
+            Action::MoveUp => {
+                vec![key(KeyCode::W), button(DPadUp), stick(LeftStickY, true)]
+            }
+            Action::MoveDown => vec![
+                key(KeyCode::S),
+                button(DPadDown),
+                stick(LeftStickY, false),
+            ],
+            Action::MoveLeft => vec![
+                key(KeyCode::A),
+                button(DPadLeft),
+                stick(LeftStickX, false),
+            ],
+            Action::MoveRight => vec![
+                key(KeyCode::D),
+                button(DPadRight),
+                stick(LeftStickX, true),
+            ],
+            Action::Sprint => vec![key(KeyCode::LShift), button(West)],
+            Action::Confirm => {
+                vec![key(KeyCode::Return), key(KeyCode::Space), button(South)]
+            }
+            Action::Cancel => vec![key(KeyCode::Escape), button(East)],
+            // Shares South with Confirm, see SHARED
+            Action::Interact => vec![key(KeyCode::E), button(South)],
+            Action::Equipment => vec![key(KeyCode::I), button(Start)],
+            Action::Minimap => vec![key(KeyCode::M), button(North)],
+            Action::Controls => vec![key(KeyCode::F1), button(Select)],
+            Action::VolumeUp => vec![key(KeyCode::Up), button(RightTrigger)],
+            Action::VolumeDown => {
+                vec![key(KeyCode::Down), button(LeftTrigger)]
+            }
+            Action::Quit => {
+                vec![Binding::Chord(vec![KeyCode::LControl, KeyCode::Q])]
+            }
+        }
+    }
+
+    /// Whether the two actions may have the same binding, see [`SHARED`].
+    pub fn shares_with(self, other: Action) -> bool {
+        self == other
+            || SHARED.iter().any(|&(a, b)| {
+                (a, b) == (self, other) || (a, b) == (other, self)
+            })
+    }
+}
+
+impl Binding {
+    fn pressed(
+        &self,
+        keyboard: &Input<KeyCode>,
+        buttons: &Input<GamepadButton>,
+        axes: &Axis<GamepadAxis>,
+        gamepads: &Gamepads,
+    ) -> bool {
+        match self {
+            Binding::Key(key) => keyboard.pressed(*key),
+            Binding::Chord(keys) => {
+                !keys.is_empty()
+                    && keys.iter().all(|key| keyboard.pressed(*key))
+            }
+            Binding::Button(button) => gamepads.iter().any(|gamepad| {
+                buttons.pressed(GamepadButton(*gamepad, *button))
+            }),
+            Binding::Axis { axis, positive } => {
+                gamepads.iter().any(|gamepad| {
+                    let value =
+                        axes.get(GamepadAxis(*gamepad, *axis)).unwrap_or(0.0);
+                    if *positive {
+                        value >= AXIS_THRESHOLD
+                    } else {
+                        value <= -AXIS_THRESHOLD
+                    }
+                })
+            }
+        }
+    }
+
+    /// Whether this and `other` come from the same kind of device, a new
+    /// binding replaces the old ones of its kind.
+    pub fn same_device(&self, other: &Binding) -> bool {
+        let gamepad = |binding: &Binding| {
+            matches!(binding, Binding::Button(_) | Binding::Axis { .. })
+        };
+        gamepad(self) == gamepad(other)
+    }
+
+    /// Short name shown on the controls screen.
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Chord(keys) => keys
+                .iter()
+                .map(|key| format!("{:?}", key))
+                .collect::<Vec<_>>()
+                .join("+"),
+            Binding::Button(button) => format!("{:?}", button),
+            Binding::Axis { axis, positive } => {
+                format!("{:?}{}", axis, if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings(
+            Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_bindings()))
+                .collect(),
+        )
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0
+            .get(&action)
+            .map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /// Binds `binding` to `action` in place of its bindings from the same
+    /// kind of device. Axes are kept when a gamepad button is bound, so the
+    /// stick keeps working. Another action bound to `binding` gets the
+    /// replaced binding instead, swapping the two, unless the actions may
+    /// share it.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.0.entry(action).or_default();
+        let replaced = bindings.iter().position(|old| {
+            old.same_device(&binding) && !matches!(old, Binding::Axis { .. })
+        });
+        let replaced = replaced.map(|index| bindings[index].clone());
+        bindings.retain(|old| {
+            !old.same_device(&binding) || matches!(old, Binding::Axis { .. })
+        });
+        bindings.insert(0, binding.clone());
+
+        for (other, bindings) in self.0.iter_mut() {
+            if other.shares_with(action) {
+                continue;
+            }
+            if let Some(index) = bindings.iter().position(|b| *b == binding) {
+                match replaced.clone() {
+                    Some(old) if !bindings.contains(&old) => {
+                        bindings[index] = old
+                    }
+                    _ => {
+                        bindings.remove(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bindings used by two actions that can't share them, as found in a
+    /// hand edited controls file.
+    pub fn duplicates(&self) -> Vec<(Action, Action, &Binding)> {
+        let mut duplicates = Vec::new();
+        for (i, (action, bindings)) in self.0.iter().enumerate() {
+            for (other, other_bindings) in self.0.iter().skip(i + 1) {
+                if action.shares_with(*other) {
+                    continue;
+                }
+                duplicates.extend(
+                    bindings
+                        .iter()
+                        .filter(|binding| other_bindings.contains(binding))
+                        .map(|binding| (*action, *other, binding)),
+                );
+            }
+        }
+        duplicates
+    }
+
+    pub fn save(&self) {
+        let text = match ron::ser::to_string_pretty(self, PrettyConfig::new()) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("Could not serialize the controls: {}", err);
+                return;
+            }
+        };
+        if let Err(err) = fs::write(BINDINGS_PATH, text) {
+            warn!("Could not save the controls to {}: {}", BINDINGS_PATH, err);
+        }
+    }
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn any_just_pressed(
+        &self,
+        actions: impl IntoIterator<Item = Action>,
+    ) -> bool {
+        actions.into_iter().any(|action| self.just_pressed(action))
+    }
+}
+
+/// Loads the saved controls, actions missing from the file keep their
+/// default bindings.
+fn load_bindings(mut commands: Commands) {
+    let mut bindings = Bindings::default();
+    if let Ok(text) = fs::read_to_string(BINDINGS_PATH) {
+        match ron::from_str::<Bindings>(&text) {
+            Ok(saved) => bindings.0.extend(saved.0),
+            Err(err) => warn!("Invalid controls in {}: {}", BINDINGS_PATH, err),
+        }
+    }
+    for (action, other, binding) in bindings.duplicates() {
+        warn!(
+            "{} is bound to both {:?} and {:?}",
+            binding.label(),
+            action,
+            other
+        );
+    }
+    commands.insert_resource(bindings);
+}
+
+fn update_actions(
+    mut state: ResMut<ActionState>,
+    bindings: Res<Bindings>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+) {
+    let pressed: HashSet<Action> = bindings
+        .0
+        .iter()
+        .filter(|(_, bindings)| {
+            bindings.iter().any(|binding| {
+                binding.pressed(&keyboard, &buttons, &axes, &gamepads)
+            })
+        })
+        .map(|(action, _)| *action)
+        .collect();
+    state.just_pressed = pressed.difference(&state.pressed).copied().collect();
+    state.pressed = pressed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_only_share_allowed_bindings() {
+        assert!(Bindings::default().duplicates().is_empty());
+    }
+
+    #[test]
+    fn rebinding_keeps_shared_bindings() {
+        let mut bindings = Bindings::default();
+        let south = Binding::Button(GamepadButtonType::South);
+        bindings.rebind(Action::Interact, south.clone());
+        assert!(bindings.get(Action::Confirm).contains(&south));
+
+        // Taking Cancel's button swaps Confirm's old one to Cancel
+        let east = Binding::Button(GamepadButtonType::East);
+        bindings.rebind(Action::Confirm, east.clone());
+        assert_eq!(bindings.get(Action::Confirm)[0], east);
+        assert!(bindings.get(Action::Cancel).contains(&south));
+        assert!(bindings.get(Action::Interact).contains(&south));
+    }
+}
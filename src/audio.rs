@@ -3,7 +3,9 @@ use bevy::prelude::*;
 use bevy_kira_audio::{Audio, AudioChannel, AudioPlugin, AudioSource};
 
 use crate::{
+    actions::{Action, ActionState},
     combat::{CombatState, FightEvent},
+    weather::{CurrentWeather, Weather},
     GameState,
 };
 
@@ -12,10 +14,13 @@ pub struct AudioState {
     combat_handle: Handle<AudioSource>,
     hit_handle: Handle<AudioSource>,
     reward_handle: Handle<AudioSource>,
+    rain_handle: Handle<AudioSource>,
+    wind_handle: Handle<AudioSource>,
 
     bgm_channel: AudioChannel,
     combat_channel: AudioChannel,
     sfx_channel: AudioChannel,
+    weather_channel: AudioChannel,
     volume: f32,
 }
 
@@ -29,6 +34,10 @@ impl Plugin for GameAudioPlugin {
                 SystemSet::on_enter(GameState::Overworld)
                     .with_system(start_bgm_music)
             )
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(play_weather_ambience),
+            )
             .add_system_set(
                 SystemSet::on_enter(GameState::Combat)
                     .with_system(start_combat_music),
@@ -75,13 +84,34 @@ fn play_hit_sfx(
     }
 }
 
+/// Loops the ambience of the current weather, switching when it changes.
+fn play_weather_ambience(
+    audio: Res<Audio>,
+    audio_state: Res<AudioState>,
+    weather: Res<CurrentWeather>,
+) {
+    if !weather.is_changed() {
+        return;
+    }
+    audio.stop_channel(&audio_state.weather_channel);
+    let ambience = match weather.0 {
+        Weather::Clear => return,
+        Weather::Rain => &audio_state.rain_handle,
+        Weather::Snow | Weather::Fog => &audio_state.wind_handle,
+    };
+    audio
+        .play_looped_in_channel(ambience.clone(), &audio_state.weather_channel);
+}
+
 fn resume_bgm_music(audio: Res<Audio>, audio_state: Res<AudioState>) {
     audio.stop_channel(&audio_state.combat_channel);
     audio.resume_channel(&audio_state.bgm_channel);
+    audio.resume_channel(&audio_state.weather_channel);
 }
 
 fn start_combat_music(audio: Res<Audio>, audio_state: Res<AudioState>) {
     audio.pause_channel(&audio_state.bgm_channel);
+    audio.pause_channel(&audio_state.weather_channel);
     audio.play_looped_in_channel(
         audio_state.combat_handle.clone(),
         &audio_state.combat_channel,
@@ -96,18 +126,22 @@ fn start_bgm_music(audio: Res<Audio>, audio_state: Res<AudioState>) {
 }
 
 fn volume_control(
-    keyboard: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     audio: Res<Audio>,
     mut audio_state: ResMut<AudioState>,
 ) {
-    if keyboard.just_pressed(KeyCode::Up) {
+    if actions.just_pressed(Action::VolumeUp) {
         audio_state.volume += 0.1;
     }
-    if keyboard.just_pressed(KeyCode::Down) {
+    if actions.just_pressed(Action::VolumeDown) {
         audio_state.volume -= 0.1;
     }
     audio_state.volume = audio_state.volume.clamp(0.0, 1.0);
     audio.set_volume_in_channel(audio_state.volume, &audio_state.bgm_channel);
+    audio.set_volume_in_channel(
+        audio_state.volume,
+        &audio_state.weather_channel,
+    );
 }
 
 fn load_audio(
@@ -119,25 +153,32 @@ fn load_audio(
     let combat_handle = assets.load("ganxta.ogg");
     let hit_handle = assets.load("hit.wav");
     let reward_handle = assets.load("reward.wav");
+    let rain_handle = assets.load("rain.wav");
+    let wind_handle = assets.load("wind.wav");
 
     let bgm_channel = AudioChannel::new("bgm".to_string());
     let combat_channel = AudioChannel::new("combat".to_string());
     let sfx_channel = AudioChannel::new("sfx".to_string());
+    let weather_channel = AudioChannel::new("weather".to_string());
 
     let volume = 0.5;
 
     audio.set_volume_in_channel(volume, &bgm_channel);
     audio.set_volume_in_channel(volume, &combat_channel);
     audio.set_volume_in_channel(volume, &sfx_channel);
+    audio.set_volume_in_channel(volume, &weather_channel);
 
     commands.insert_resource(AudioState {
         bgm_handle,
         combat_handle,
         hit_handle,
         reward_handle,
+        rain_handle,
+        wind_handle,
         bgm_channel,
         combat_channel,
         sfx_channel,
+        weather_channel,
         volume,
     });
 }
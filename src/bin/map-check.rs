@@ -0,0 +1,104 @@
+//! Validates map files offline, e.g. `cargo run --bin map-check <file>...`.
+//! Warps are checked against `assets/maps.ron` and the maps it registers.
+//! Exits with a non-zero status when any map has errors.
+
+use std::{collections::HashMap, env, fs, io, path::Path, process};
+
+use bevy_tutorial::{
+    map::{MapData, MapRegistry},
+    tiled::{check_tiled_map, TiledFormat},
+    validation::{check_map, check_warps, warp_error, Diagnostic, Severity},
+};
+
+const REGISTRY: &str = "assets/maps.ron";
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: map-check <file>...");
+        process::exit(2);
+    }
+
+    let registry = load_registry();
+    let registered = registry.as_ref().map_or_else(HashMap::new, |registry| {
+        registry
+            .maps
+            .iter()
+            .filter_map(|(id, path)| {
+                let path = Path::new("assets").join(path);
+                let (map, _) = check_file(&path).ok()?;
+                Some((id.clone(), map?))
+            })
+            .collect()
+    });
+
+    let mut failed = false;
+    for path in paths.iter() {
+        let path = Path::new(path);
+        let (map, mut diagnostics) = match check_file(path) {
+            Ok(checked) => checked,
+            Err(err) => {
+                eprintln!("{}: error: {}", path.display(), err);
+                failed = true;
+                continue;
+            }
+        };
+        if let (Some(map), Some(registry)) = (map, registry.as_ref()) {
+            diagnostics.extend(check_warps(&map, registry, &registered));
+        }
+
+        for diagnostic in diagnostics.iter() {
+            eprintln!("{}:{}", path.display(), diagnostic);
+        }
+        failed |= diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error);
+    }
+
+    if let Some(registry) = registry.as_ref() {
+        let mut generated: Vec<_> = registry.generated.iter().collect();
+        generated.sort_by_key(|(id, _)| id.as_str());
+        for (id, config) in generated {
+            if let Some(message) =
+                warp_error(&config.exit, registry, &registered)
+            {
+                eprintln!("{}: error: exit of '{}': {}", REGISTRY, id, message);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// The map registry, `None` with a warning when it can't be read so single
+/// maps can still be checked anywhere.
+fn load_registry() -> Option<MapRegistry> {
+    let text = match fs::read_to_string(REGISTRY) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("{}: warning: {}, warps not checked", REGISTRY, err);
+            return None;
+        }
+    };
+    match ron::from_str(&text) {
+        Ok(registry) => Some(registry),
+        Err(err) => {
+            eprintln!("{}: error: {}", REGISTRY, err);
+            process::exit(1);
+        }
+    }
+}
+
+fn check_file(path: &Path) -> io::Result<(Option<MapData>, Vec<Diagnostic>)> {
+    let text = fs::read_to_string(path)?;
+    Ok(match TiledFormat::from_path(path) {
+        Some(format) => {
+            let name = path.file_stem().unwrap_or_default();
+            check_tiled_map(&text, format, &name.to_string_lossy())
+        }
+        None => check_map(&text),
+    })
+}
@@ -0,0 +1,57 @@
+//! Writes a generated dungeon as a map file, e.g.
+//! `cargo run --bin map-gen rooms 40 25 1234 assets/maps/crypt.map.txt`.
+//! The exit warp leads to the overworld, edit the header to change it.
+
+use std::{env, fs, process};
+
+use bevy_tutorial::{
+    generator::{generate, Algorithm, GeneratorConfig},
+    tilemap::Warp,
+};
+
+const USAGE: &str =
+    "usage: map-gen <rooms|caves> <width> <height> <seed> [output]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 4 {
+        exit_with_usage();
+    }
+
+    let algorithm = match args[0].as_str() {
+        "rooms" => Algorithm::RoomsAndCorridors,
+        "caves" => Algorithm::CellularAutomata,
+        _ => exit_with_usage(),
+    };
+    let number = |arg: &String| -> u64 {
+        arg.parse().unwrap_or_else(|_| exit_with_usage())
+    };
+    let config = GeneratorConfig {
+        algorithm,
+        width: number(&args[1]) as usize,
+        height: number(&args[2]) as usize,
+        seed: None,
+        exit: Warp {
+            map: "overworld".to_string(),
+            spawn: "from_dungeon".to_string(),
+        },
+    };
+
+    let map = generate(&config, number(&args[3]));
+    let text = map.to_map_file().expect("Failed to write map header");
+
+    match args.get(4) {
+        Some(path) => {
+            if let Err(err) = fs::write(path, text) {
+                eprintln!("{}: error: {}", path, err);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", text),
+    }
+}
+
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,355 @@
+use std::collections::HashSet;
+
+use bevy::{
+    prelude::*,
+    render::{
+        camera::Camera2d,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::{
+    ascii::AsciiSheet,
+    clock::GameClock,
+    graphics::{lit, shade},
+    map::rgb,
+    tilemap::{tile_translation, GridTile, Map, TileGrid},
+    GameState, TILE_SIZE,
+};
+
+/// Width and height of a chunk in tiles.
+pub const CHUNK_SIZE: usize = 16;
+/// Chunks kept spawned around the ones in view, so they are drawn before
+/// the camera gets to them.
+const STREAM_MARGIN: i32 = 1;
+/// Shortest frame time animations are played at.
+const MIN_FRAME_TIME: f32 = 0.01;
+
+/// Sprite drawing the tiles of one chunk of the [`TileGrid`] as a single
+/// image, redrawn when its tiles change or animate, when the daylight
+/// changes and when the ASCII sheet is reloaded.
+#[derive(Component)]
+pub struct TileChunk {
+    pub x: usize,
+    pub y: usize,
+    /// Shortest frame time of the animated tiles in the chunk.
+    frame_time: Option<f32>,
+    /// Animation frame the chunk was last drawn at, `None` until drawn.
+    drawn_frame: Option<usize>,
+}
+
+pub struct ChunkPlugin;
+
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        // After every system that changes tiles or the map during the frame
+        app.add_system_set_to_stage(
+            CoreStage::PostUpdate,
+            SystemSet::on_update(GameState::Overworld)
+                .with_system(stream_chunks),
+        )
+        .add_system_to_stage(CoreStage::PostUpdate, draw_chunks);
+    }
+}
+
+/// Spawns the sprite of chunk `(x, y)`, drawn once the ASCII sheet has
+/// loaded.
+fn spawn_chunk(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    atlas: &TextureAtlas,
+    grid: &TileGrid,
+    (x, y): (usize, usize),
+) -> Entity {
+    let cell = atlas.textures[0].max - atlas.textures[0].min;
+    let size = Extent3d {
+        width: CHUNK_SIZE as u32 * cell.x as u32,
+        height: CHUNK_SIZE as u32 * cell.y as u32,
+        depth_or_array_layers: 1,
+    };
+    let image = images.add(Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+    // Chunk sprites are centered between their first and last tile
+    let center = (CHUNK_SIZE - 1) as f32 / 2.0 * TILE_SIZE;
+    let translation = tile_translation(x * CHUNK_SIZE, y * CHUNK_SIZE)
+        + Vec2::new(center, -center);
+    let frame_time = chunk_tiles(grid, x, y)
+        .filter_map(|(_, tile)| tile?.animation.as_ref())
+        .map(|animation| animation.frame_time)
+        .reduce(f32::min);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(CHUNK_SIZE as f32 * TILE_SIZE)),
+                ..default()
+            },
+            texture: image,
+            transform: Transform::from_translation(translation.extend(100.0)),
+            ..default()
+        })
+        .insert(Name::new(format!("Chunk {} {}", x, y)))
+        .insert(TileChunk {
+            x,
+            y,
+            frame_time,
+            drawn_frame: None,
+        })
+        .id()
+}
+
+/// First and last chunk in view of the camera, plus the margin, or `None`
+/// for an empty grid.
+fn chunks_in_view(
+    grid: &TileGrid,
+    camera: &Transform,
+    projection: &OrthographicProjection,
+) -> Option<((usize, usize), (usize, usize))> {
+    let chunks_x = (grid.width + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let chunks_y = (grid.height + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    if chunks_x == 0 || chunks_y == 0 {
+        return None;
+    }
+
+    let half = Vec2::new(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    ) * projection.scale
+        / 2.0;
+    let (left, top) = TileGrid::tile_coords(
+        camera.translation + Vec3::new(-half.x, half.y, 0.0),
+    );
+    let (right, bottom) = TileGrid::tile_coords(
+        camera.translation + Vec3::new(half.x, -half.y, 0.0),
+    );
+    let chunk = |tile: i32, chunks: usize, margin: i32| {
+        (tile.div_euclid(CHUNK_SIZE as i32) + margin)
+            .clamp(0, chunks as i32 - 1) as usize
+    };
+
+    Some((
+        (
+            chunk(left, chunks_x, -STREAM_MARGIN),
+            chunk(top, chunks_y, -STREAM_MARGIN),
+        ),
+        (
+            chunk(right, chunks_x, STREAM_MARGIN),
+            chunk(bottom, chunks_y, STREAM_MARGIN),
+        ),
+    ))
+}
+
+/// Keeps only the chunks around the camera spawned. Tiles live in the grid,
+/// so chunks spawned again show every change made to them meanwhile.
+fn stream_chunks(
+    mut commands: Commands,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    map_query: Query<Entity, With<Map>>,
+    chunk_query: Query<(Entity, &TileChunk)>,
+    grid: Res<TileGrid>,
+    mut images: ResMut<Assets<Image>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    ascii: Res<AsciiSheet>,
+) {
+    let map = match map_query.get_single() {
+        Ok(map) => map,
+        Err(_) => return,
+    };
+    let (camera, projection) = camera_query.single();
+    let in_view = chunks_in_view(&grid, camera, projection);
+    let visible = |(x, y): (usize, usize)| {
+        in_view.map_or(false, |(min, max)| {
+            (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&y)
+        })
+    };
+
+    let mut loaded = HashSet::new();
+    for (entity, chunk) in chunk_query.iter() {
+        if visible((chunk.x, chunk.y)) {
+            loaded.insert((chunk.x, chunk.y));
+        } else {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let (min, max) = match in_view {
+        Some(in_view) => in_view,
+        None => return,
+    };
+    let atlas = atlases.get(&ascii.0).expect("Ascii sheet not loaded!");
+    for y in min.1..=max.1 {
+        for x in min.0..=max.0 {
+            if !loaded.contains(&(x, y)) {
+                let chunk = spawn_chunk(
+                    &mut commands,
+                    &mut images,
+                    atlas,
+                    &grid,
+                    (x, y),
+                );
+                commands.entity(map).add_child(chunk);
+            }
+        }
+    }
+}
+
+/// Tile positions inside the chunk, with the tile if the map has one there.
+fn chunk_tiles(
+    grid: &TileGrid,
+    chunk_x: usize,
+    chunk_y: usize,
+) -> impl Iterator<Item = ((usize, usize), Option<&GridTile>)> + '_ {
+    (0..CHUNK_SIZE).flat_map(move |y| {
+        (0..CHUNK_SIZE).map(move |x| {
+            let (tile_x, tile_y) =
+                (chunk_x * CHUNK_SIZE + x, chunk_y * CHUNK_SIZE + y);
+            ((x, y), grid.get(tile_x as i32, tile_y as i32))
+        })
+    })
+}
+
+fn draw_chunks(
+    mut grid: ResMut<TileGrid>,
+    mut chunk_query: Query<(&mut TileChunk, &Handle<Image>)>,
+    mut images: ResMut<Assets<Image>>,
+    atlases: Res<Assets<TextureAtlas>>,
+    ascii: Res<AsciiSheet>,
+    time: Res<Time>,
+    clock: Res<GameClock>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut sheet: Local<Option<Image>>,
+    mut drawn_tint: Local<Option<Color>>,
+) {
+    let atlas = match atlases.get(&ascii.0) {
+        Some(atlas) => atlas,
+        None => return,
+    };
+    // The sheet is hot reloaded like the other assets
+    let reloaded = image_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => *handle == atlas.texture,
+        _ => false,
+    });
+    if sheet.is_none() || reloaded {
+        // Chunks are drawn into other images of the same asset collection,
+        // so keep a copy of the sheet to read glyphs from
+        *sheet = images.get(&atlas.texture).cloned();
+    }
+    let sheet = match &*sheet {
+        Some(sheet) => sheet,
+        None => return,
+    };
+
+    // Only borrowed mutably when there is something to take, so systems
+    // watching the grid for changes don't run every frame
+    let dirty = if grid.has_dirty_chunks() {
+        grid.take_dirty_chunks()
+    } else {
+        default()
+    };
+    let elapsed = time.seconds_since_startup() as f32;
+    let tint = clock.tint();
+    let redraw = reloaded || *drawn_tint != Some(tint);
+    *drawn_tint = Some(tint);
+
+    for (mut chunk, handle) in chunk_query.iter_mut() {
+        let frame = chunk
+            .frame_time
+            .map_or(0, |frame_time| animation_frame(elapsed, frame_time));
+        if chunk.drawn_frame == Some(frame)
+            && !redraw
+            && !dirty.contains(&(chunk.x, chunk.y))
+        {
+            continue;
+        }
+        let image = match images.get_mut(handle) {
+            Some(image) => image,
+            None => continue,
+        };
+        chunk.drawn_frame = Some(frame);
+
+        for ((x, y), tile) in chunk_tiles(&grid, chunk.x, chunk.y) {
+            let (index, color) = match tile {
+                Some(tile) => {
+                    let offset =
+                        chunk.x * CHUNK_SIZE + x + chunk.y * CHUNK_SIZE + y;
+                    appearance(tile, elapsed, offset, tint)
+                }
+                None => (0, Color::NONE),
+            };
+            draw_glyph(image, sheet, atlas, (x, y), index, color);
+        }
+    }
+}
+
+/// Glyph and color of `tile` after `elapsed` seconds, with its animation
+/// started `offset` frames in so neighbouring tiles don't all change at once,
+/// lit by the daylight `tint`.
+fn appearance(
+    tile: &GridTile,
+    elapsed: f32,
+    offset: usize,
+    tint: Color,
+) -> (usize, Color) {
+    let mut index = tile.index;
+    let mut color = tile.color;
+    if let Some(animation) = &tile.animation {
+        let frame = animation_frame(elapsed, animation.frame_time) + offset;
+        if !animation.frames.is_empty() {
+            index = animation.frames[frame % animation.frames.len()];
+        }
+        if !animation.colors.is_empty() {
+            color = rgb(animation.colors[frame % animation.colors.len()]);
+        }
+    }
+    (index, lit(shade(color, tile.brightness), tint, tile.light))
+}
+
+/// Frames shown after `elapsed` seconds. Maps are validated for positive
+/// frame times, a zero or NaN one still must not overflow the frame count.
+fn animation_frame(elapsed: f32, frame_time: f32) -> usize {
+    (elapsed / frame_time.max(MIN_FRAME_TIME)) as usize
+}
+
+/// Copies glyph `index` of the sheet into the cell of tile `(x, y)`, tinted
+/// like sprites are. Both images are RGBA with 4 bytes per pixel.
+fn draw_glyph(
+    image: &mut Image,
+    sheet: &Image,
+    atlas: &TextureAtlas,
+    (x, y): (usize, usize),
+    index: usize,
+    color: Color,
+) {
+    // Glyphs missing from the sheet clear the cell, so the old one doesn't
+    // stay drawn
+    let (rect, color) = match atlas.textures.get(index) {
+        Some(rect) => (rect, color),
+        None => (&atlas.textures[0], Color::NONE),
+    };
+    let (width, height) = (
+        (rect.max.x - rect.min.x) as usize,
+        (rect.max.y - rect.min.y) as usize,
+    );
+    let sheet_width = sheet.texture_descriptor.size.width as usize;
+    let image_width = image.texture_descriptor.size.width as usize;
+    let tint = color.as_rgba_f32();
+
+    for row in 0..height {
+        for column in 0..width {
+            let from = ((rect.min.y as usize + row) * sheet_width
+                + rect.min.x as usize
+                + column)
+                * 4;
+            let to =
+                ((y * height + row) * image_width + x * width + column) * 4;
+            for channel in 0..4 {
+                let value = sheet.data[from + channel] as f32 * tint[channel];
+                image.data[to + channel] = value as u8;
+            }
+        }
+    }
+}
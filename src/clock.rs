@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{graphics::lit, player::Player, tilemap::TileGrid, GameState};
+
+/// Real seconds a full in-game day takes.
+const DAY_SECONDS: f32 = 600.0;
+const DAWN: f32 = 6.0;
+const DUSK: f32 = 18.0;
+/// Hours the light takes to change around dawn and dusk.
+const TWILIGHT_HOURS: f32 = 2.0;
+/// Daylight changes in steps, so chunks are not redrawn every frame.
+const LIGHT_STEPS: f32 = 8.0;
+const NIGHT_TINT: Color = Color::rgb(0.3, 0.35, 0.6);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeOfDay {
+    Day,
+    Night,
+}
+
+/// In-game time, only running while the player is in the overworld.
+pub struct GameClock {
+    /// Hour of the day, from 0 to 24.
+    pub hour: f32,
+    pub hours_per_second: f32,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock {
+            hour: 8.0,
+            hours_per_second: 24.0 / DAY_SECONDS,
+        }
+    }
+}
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameClock>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(advance_clock)
+                    .with_system(tint_player.after(advance_clock)),
+            )
+            .add_system_set(
+                SystemSet::on_pause(GameState::Overworld)
+                    .with_system(untint_player),
+            );
+    }
+}
+
+impl GameClock {
+    pub fn time_of_day(&self) -> TimeOfDay {
+        if (DAWN..DUSK).contains(&self.hour) {
+            TimeOfDay::Day
+        } else {
+            TimeOfDay::Night
+        }
+    }
+
+    /// How bright it is outside, from 0 at night to 1 during the day.
+    pub fn daylight(&self) -> f32 {
+        let half = TWILIGHT_HOURS / 2.0;
+        let sunrise = (self.hour - (DAWN - half)) / TWILIGHT_HOURS;
+        let sunset = (DUSK + half - self.hour) / TWILIGHT_HOURS;
+        let daylight = sunrise.min(sunset).clamp(0.0, 1.0);
+        (daylight * LIGHT_STEPS).round() / LIGHT_STEPS
+    }
+
+    /// Color unlit tiles and sprites are multiplied by.
+    pub fn tint(&self) -> Color {
+        let daylight = self.daylight();
+        let channel = |night: f32| night + (1.0 - night) * daylight;
+        Color::rgb(
+            channel(NIGHT_TINT.r()),
+            channel(NIGHT_TINT.g()),
+            channel(NIGHT_TINT.b()),
+        )
+    }
+}
+
+fn advance_clock(mut clock: ResMut<GameClock>, time: Res<Time>) {
+    let hours = time.delta_seconds() * clock.hours_per_second;
+    clock.hour = (clock.hour + hours).rem_euclid(24.0);
+}
+
+/// Darkens the player like the tile they stand on.
+fn tint_player(
+    mut player_query: Query<
+        (&mut TextureAtlasSprite, &Transform),
+        With<Player>,
+    >,
+    grid: Res<TileGrid>,
+    clock: Res<GameClock>,
+) {
+    let (mut sprite, transform) = player_query.single_mut();
+    let light = grid
+        .tile_at(transform.translation)
+        .map_or(0.0, |tile| tile.light);
+    let color = lit(Color::WHITE, clock.tint(), light);
+    if sprite.color != color {
+        sprite.color = color;
+    }
+}
+
+fn untint_player(
+    mut player_query: Query<&mut TextureAtlasSprite, With<Player>>,
+) {
+    player_query.single_mut().color = Color::WHITE;
+}
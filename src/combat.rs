@@ -1,14 +1,22 @@
+use std::cmp::Ordering;
+
 use bevy::{prelude::*, render::camera::Camera2d};
 use bevy_inspector_egui::Inspectable;
+use serde::Deserialize;
 
 use crate::{
+    actions::{Action, ActionState},
     ascii::{
-        spawn_ascii_text, spawn_nine_slice, AsciiSheet, NineSlice,
-        NineSliceIndicies,
+        spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, AsciiSheet,
+        NineSlice, NineSliceIndicies,
     },
+    clock::GameClock,
+    encounter::{EncounterTables, PendingEncounter},
+    equipment::{derive_stats, EffectiveStats, StatBonus},
     fadeout::create_fadeout,
     graphics::{spawn_enemy_sprite, CharacterSheet},
     player::Player,
+    weather::CurrentWeather,
     GameState, RESOLUTION, TILE_SIZE,
 };
 
@@ -19,12 +27,18 @@ pub struct FightEvent {
 }
 
 const MENU_COUNT: isize = 2;
+/// Widest gap between enemies, bigger groups are squeezed onto the screen.
+const ENEMY_SPACING: f32 = 1.0;
+/// Kept free on both sides of the outermost enemies.
+const ENEMY_MARGIN: f32 = 0.5;
+const TARGET_CURSOR_GLYPH: usize = 'v' as usize;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct CombatMenuSelection {
     selected: CombatMenuOption,
 }
 
+/// Base stats and current health, `EffectiveStats` adds equipment to them.
 #[derive(Component, Inspectable)]
 pub struct CombatStats {
     pub health: isize,
@@ -33,6 +47,7 @@ pub struct CombatStats {
     pub defense: isize,
 }
 
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EnemyType {
     Bat,
     Ghost,
@@ -41,6 +56,40 @@ pub enum EnemyType {
 #[derive(Component)]
 pub struct Enemy {
     pub enemy_type: EnemyType,
+    pub level: usize,
+}
+
+impl EnemyType {
+    fn name(&self) -> &'static str {
+        match self {
+            EnemyType::Bat => "Bat",
+            EnemyType::Ghost => "Ghost",
+        }
+    }
+
+    fn stats(&self, level: usize) -> CombatStats {
+        let (health, attack, defense) = match self {
+            EnemyType::Bat => (3, 2, 1),
+            EnemyType::Ghost => (5, 3, 2),
+        };
+        let bonus = level as isize - 1;
+        CombatStats {
+            health: health + 2 * bonus,
+            max_health: health + 2 * bonus,
+            attack: attack + bonus,
+            defense: defense + bonus / 2,
+        }
+    }
+}
+
+impl Enemy {
+    fn exp_reward(&self) -> usize {
+        let exp = match self.enemy_type {
+            EnemyType::Bat => 10,
+            EnemyType::Ghost => 30,
+        };
+        exp * self.level
+    }
 }
 
 #[derive(Component, Clone, Copy, PartialEq, Eq)]
@@ -56,10 +105,14 @@ pub enum CombatState {
     EnemyTurn(bool),
     EnemyAttack,
     Reward,
+    /// The player lost, nothing is earned.
+    Defeat,
     Exiting,
 }
 
 pub struct AttackEffects {
+    /// Enemy flashing while the player attack plays out.
+    target: Option<Entity>,
     timer: Timer,
     flash_speed: f32,
     screen_shake_amount: f32,
@@ -69,6 +122,18 @@ pub struct AttackEffects {
 #[derive(Component)]
 pub struct CombatText;
 
+/// Enemy the player attacks, picked with left and right after choosing
+/// Fight.
+#[derive(Default)]
+pub struct CombatTarget {
+    choosing: bool,
+    enemy: Option<Entity>,
+}
+
+/// Marker over the enemy being picked.
+#[derive(Component)]
+pub struct TargetCursor;
+
 pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
@@ -76,6 +141,7 @@ impl Plugin for CombatPlugin {
         app.add_event::<FightEvent>()
             .add_state(CombatState::PlayerTurn)
             .insert_resource(AttackEffects {
+                target: None,
                 timer: Timer::from_seconds(0.7, true),
                 flash_speed: 0.1,
                 screen_shake_amount: 0.1,
@@ -84,14 +150,16 @@ impl Plugin for CombatPlugin {
             .insert_resource(CombatMenuSelection {
                 selected: CombatMenuOption::Fight,
             })
+            .init_resource::<CombatTarget>()
             .add_system_set(
                 SystemSet::on_update(CombatState::EnemyTurn(false))
-                    .with_system(process_enemy_turn),
+                    .with_system(process_enemy_turn.after(derive_stats)),
             )
             .add_system_set(
                 SystemSet::on_update(GameState::Combat)
-                    .with_system(combat_input)
-                    .with_system(combat_damage_calc)
+                    .with_system(combat_input.after(derive_stats))
+                    .with_system(draw_target_cursor.after(combat_input))
+                    .with_system(combat_damage_calc.after(derive_stats))
                     .with_system(combat_camera)
                     .with_system(highlight_combat_buttons),
             )
@@ -120,6 +188,14 @@ impl Plugin for CombatPlugin {
                 SystemSet::on_update(CombatState::Reward)
                     .with_system(handle_accepting_reward),
             )
+            .add_system_set(
+                SystemSet::on_enter(CombatState::Defeat)
+                    .with_system(player_defeat),
+            )
+            .add_system_set(
+                SystemSet::on_update(CombatState::Defeat)
+                    .with_system(handle_accepting_reward),
+            )
             .add_system_set(
                 SystemSet::on_update(CombatState::EnemyAttack)
                     .with_system(handle_attack_effects),
@@ -130,10 +206,10 @@ impl Plugin for CombatPlugin {
 fn handle_accepting_reward(
     mut commands: Commands,
     ascii: Res<AsciiSheet>,
-    keyboard: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     mut combat_state: ResMut<State<CombatState>>,
 ) {
-    if keyboard.just_pressed(KeyCode::Space) {
+    if actions.just_pressed(Action::Confirm) {
         combat_state.set(CombatState::Exiting).unwrap();
         create_fadeout(&mut commands, None, &ascii);
     }
@@ -142,14 +218,14 @@ fn handle_accepting_reward(
 fn give_reward(
     mut commands: Commands,
     ascii: Res<AsciiSheet>,
-    mut player_query: Query<(&mut Player, &mut CombatStats)>,
-    mut enemy_query: Query<&Enemy>
+    mut player_query: Query<(&mut Player, &mut CombatStats), Without<Enemy>>,
+    enemy_query: Query<(&Enemy, &CombatStats)>,
 ) {
-    // TODO: based on enemies killed
-    let exp_reward = match enemy_query.single().enemy_type {
-        EnemyType::Bat => 10,
-        EnemyType::Ghost => 30,
-    };
+    let exp_reward = enemy_query
+        .iter()
+        .filter(|(_, stats)| stats.health <= 0)
+        .map(|(enemy, _)| enemy.exp_reward())
+        .sum();
     let reward_text = format!("Earmed: {} exp", exp_reward);
     let text = spawn_ascii_text(
         &mut commands,
@@ -175,6 +251,23 @@ fn give_reward(
     };
 }
 
+/// Sends the player back to the overworld with 1 health and no exp.
+fn player_defeat(
+    mut commands: Commands,
+    ascii: Res<AsciiSheet>,
+    mut player_query: Query<&mut CombatStats, With<Player>>,
+) {
+    let defeat_text = "Defeated...";
+    let text = spawn_ascii_text(
+        &mut commands,
+        &ascii,
+        defeat_text,
+        Vec3::new(-((defeat_text.len() / 2) as f32 * TILE_SIZE), 0.0, 0.0),
+    );
+    commands.entity(text).insert(CombatText);
+    player_query.single_mut().health = 1;
+}
+
 fn despawn_all_combat_text(
     mut commands: Commands,
     text_query: Query<Entity, With<CombatText>>,
@@ -203,21 +296,26 @@ fn spawn_player_health(
 }
 
 fn handle_attack_effects(
+    mut commands: Commands,
     mut attack_fx: ResMut<AttackEffects>,
     time: Res<Time>,
-    mut enemy_graphics_query: Query<&mut Visibility, With<Enemy>>,
+    mut enemy_graphics_query: Query<
+        (&mut Visibility, &CombatStats, &Children),
+        With<Enemy>,
+    >,
+    text_query: Query<Entity, With<CombatText>>,
     mut state: ResMut<State<CombatState>>,
 ) {
     attack_fx.timer.tick(time.delta());
-    let mut enemy_sprite = enemy_graphics_query.iter_mut().next().unwrap();
+    let mut target = attack_fx
+        .target
+        .and_then(|target| enemy_graphics_query.get_mut(target).ok());
 
     if state.current() == &CombatState::PlayerAttack {
-        if attack_fx.timer.elapsed_secs() % attack_fx.flash_speed
-            > attack_fx.flash_speed / 2.0
-        {
-            enemy_sprite.is_visible = false;
-        } else {
-            enemy_sprite.is_visible = true;
+        if let Some((enemy_sprite, _, _)) = target.as_mut() {
+            enemy_sprite.is_visible = attack_fx.timer.elapsed_secs()
+                % attack_fx.flash_speed
+                <= attack_fx.flash_speed / 2.0;
         }
     } else {
         attack_fx.current_shake = attack_fx.screen_shake_amount
@@ -225,7 +323,18 @@ fn handle_attack_effects(
     }
 
     if attack_fx.timer.just_finished() {
-        enemy_sprite.is_visible = true;
+        // Defeated enemies stay hidden for the rest of the fight
+        if let Some((mut enemy_sprite, stats, children)) = target {
+            enemy_sprite.is_visible = stats.health > 0;
+            // Children keep their own visibility, so the health text goes
+            if stats.health <= 0 {
+                for &child in children.iter() {
+                    if text_query.get(child).is_ok() {
+                        commands.entity(child).despawn_recursive();
+                    }
+                }
+            }
+        }
         if state.current() == &CombatState::PlayerAttack {
             state.set(CombatState::EnemyTurn(false)).unwrap();
         } else {
@@ -234,27 +343,33 @@ fn handle_attack_effects(
     }
 }
 
-fn start_combat(mut combat_state: ResMut<State<CombatState>>) {
+fn start_combat(
+    mut combat_state: ResMut<State<CombatState>>,
+    mut target: ResMut<CombatTarget>,
+) {
     // TODO: speed and turn calculatins
     // thorw away error if it happens
     let _ = combat_state.set(CombatState::PlayerTurn);
+    *target = CombatTarget::default();
 }
 
 fn process_enemy_turn(
     mut fight_event: EventWriter<FightEvent>,
     mut combat_state: ResMut<State<CombatState>>,
-    enemy_query: Query<&CombatStats, With<Enemy>>,
+    enemy_query: Query<(&CombatStats, &EffectiveStats), With<Enemy>>,
     player_query: Query<Entity, With<Player>>,
 ) {
     let player_ent = player_query.single();
-    // TODO: support multiple enemies
-    let enemy_stats = enemy_query.iter().next().unwrap();
 
-    fight_event.send(FightEvent {
-        target: player_ent,
-        damage_amount: enemy_stats.attack,
-        next_state: CombatState::EnemyAttack,
-    });
+    for (_, enemy_stats) in
+        enemy_query.iter().filter(|(stats, _)| stats.health > 0)
+    {
+        fight_event.send(FightEvent {
+            target: player_ent,
+            damage_amount: enemy_stats.attack,
+            next_state: CombatState::EnemyAttack,
+        });
+    }
     combat_state.set(CombatState::EnemyTurn(true));
 }
 
@@ -363,20 +478,39 @@ fn spawn_combat_menu(
 fn combat_damage_calc(
     mut commands: Commands,
     mut fight_event: EventReader<FightEvent>,
-    mut target_query: Query<(&Children, &mut CombatStats)>,
+    mut target_query: Query<(&Children, &mut CombatStats, &EffectiveStats)>,
+    enemy_query: Query<Entity, With<Enemy>>,
     ascii: Res<AsciiSheet>,
     text_query: Query<&Transform, With<CombatText>>,
     mut combat_state: ResMut<State<CombatState>>,
+    mut attack_fx: ResMut<AttackEffects>,
 ) {
-    if let Some(event) = fight_event.iter().next() {
-        let (target_children, mut stats) = target_query
+    // Every living enemy attacks at once, so there can be several events
+    let events: Vec<&FightEvent> = fight_event.iter().collect();
+    let last_event = match events.last() {
+        Some(event) => *event,
+        None => return,
+    };
+
+    let mut targets = Vec::new();
+    for event in events.iter() {
+        let (_, mut stats, effective) = target_query
             .get_mut(event.target)
             .expect("target has no stats");
 
-        stats.health = std::cmp::max(
-            stats.health - (event.damage_amount - stats.defense),
-            0,
-        );
+        // Defense above the attack blocks it, it never heals
+        let damage = (event.damage_amount - effective.defense).max(0);
+        stats.health = std::cmp::max(stats.health - damage, 0);
+        if !targets.contains(&event.target) {
+            targets.push(event.target);
+        }
+    }
+
+    let mut player_defeated = false;
+    for target in targets.iter() {
+        let (target_children, stats, _) = target_query.get(*target).unwrap();
+        player_defeated |=
+            stats.health == 0 && enemy_query.get(*target).is_err();
 
         for child in target_children.iter() {
             if let Ok(transform) = text_query.get(*child) {
@@ -389,26 +523,35 @@ fn combat_damage_calc(
                     transform.translation,
                 );
                 commands.entity(new_health).insert(CombatText);
-                commands.entity(event.target).add_child(new_health);
+                commands.entity(*target).add_child(new_health);
             }
         }
+    }
 
-        if stats.health == 0 {
-            // create_fadeout(&mut commands, GameState::Overworld, &ascii);
-            combat_state.set(CombatState::Reward);
-        } else {
-            combat_state.set(event.next_state);
-        }
+    let enemies_left = enemy_query.iter().any(|enemy| {
+        target_query
+            .get(enemy)
+            .map_or(false, |(_, stats, _)| stats.health > 0)
+    });
+
+    if player_defeated {
+        combat_state.set(CombatState::Defeat);
+    } else if !enemies_left {
+        combat_state.set(CombatState::Reward);
+    } else {
+        attack_fx.target = Some(last_event.target);
+        combat_state.set(last_event.next_state);
     }
 }
 
 fn combat_input(
     mut commands: Commands,
-    keyboard: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
     mut fight_event: EventWriter<FightEvent>,
-    player_query: Query<&CombatStats, With<Player>>,
-    enemy_query: Query<Entity, With<Enemy>>,
+    player_query: Query<&EffectiveStats, With<Player>>,
+    enemy_query: Query<(Entity, &CombatStats, &Transform), With<Enemy>>,
     mut menu_state: ResMut<CombatMenuSelection>,
+    mut target: ResMut<CombatTarget>,
     ascii: Res<AsciiSheet>,
     combat_state: Res<State<CombatState>>,
 ) {
@@ -416,12 +559,54 @@ fn combat_input(
         return;
     }
 
+    if target.choosing {
+        // Living enemies from left to right, the way the cursor moves
+        let mut living: Vec<(Entity, f32)> = enemy_query
+            .iter()
+            .filter(|(_, stats, _)| stats.health > 0)
+            .map(|(enemy, _, transform)| (enemy, transform.translation.x))
+            .collect();
+        living.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        if living.is_empty() {
+            target.choosing = false;
+            return;
+        }
+
+        let count = living.len();
+        let current = living
+            .iter()
+            .position(|(enemy, _)| Some(*enemy) == target.enemy)
+            .unwrap_or_default();
+        let next = if actions.just_pressed(Action::MoveLeft) {
+            (current + count - 1) % count
+        } else if actions.just_pressed(Action::MoveRight) {
+            (current + 1) % count
+        } else {
+            current
+        };
+        let enemy = living[next].0;
+        target.enemy = Some(enemy);
+
+        if actions.just_pressed(Action::Cancel) {
+            target.choosing = false;
+        } else if actions.just_pressed(Action::Confirm) {
+            target.choosing = false;
+            let player_stats = player_query.single();
+            fight_event.send(FightEvent {
+                target: enemy,
+                damage_amount: player_stats.attack,
+                next_state: CombatState::PlayerAttack,
+            });
+        }
+        return;
+    }
+
     let mut new_selection = menu_state.selected as isize;
 
-    if keyboard.just_pressed(KeyCode::A) {
+    if actions.just_pressed(Action::MoveLeft) {
         new_selection -= 1;
     }
-    if keyboard.just_pressed(KeyCode::D) {
+    if actions.just_pressed(Action::MoveRight) {
         new_selection += 1;
     }
 
@@ -433,17 +618,9 @@ fn combat_input(
         _ => unreachable!("Bad menu selection"),
     };
 
-    if keyboard.just_pressed(KeyCode::Return) {
+    if actions.just_pressed(Action::Confirm) {
         match menu_state.selected {
-            CombatMenuOption::Fight => {
-                let enemy = enemy_query.iter().next().unwrap();
-                let player_stats = player_query.single();
-                fight_event.send(FightEvent {
-                    target: enemy,
-                    damage_amount: player_stats.attack,
-                    next_state: CombatState::PlayerAttack,
-                });
-            }
+            CombatMenuOption::Fight => target.choosing = true,
             CombatMenuOption::Run => {
                 create_fadeout(&mut commands, None, &ascii);
             }
@@ -451,6 +628,28 @@ fn combat_input(
     }
 }
 
+/// Shows the cursor over the enemy being picked, hidden otherwise.
+fn draw_target_cursor(
+    target: Res<CombatTarget>,
+    enemy_query: Query<&Transform, (With<Enemy>, Without<TargetCursor>)>,
+    mut cursor_query: Query<
+        (&mut Transform, &mut Visibility),
+        With<TargetCursor>,
+    >,
+) {
+    let enemy = target
+        .enemy
+        .filter(|_| target.choosing)
+        .and_then(|enemy| enemy_query.get(enemy).ok());
+    for (mut transform, mut visibility) in cursor_query.iter_mut() {
+        visibility.is_visible = enemy.is_some();
+        if let Some(enemy) = enemy {
+            transform.translation.x = enemy.translation.x;
+            transform.translation.y = enemy.translation.y + 0.35;
+        }
+    }
+}
+
 fn combat_camera(
     mut camera_query: Query<&mut Transform, With<Camera2d>>,
     attack_fx: ResMut<AttackEffects>,
@@ -464,50 +663,69 @@ fn spawn_enemy(
     mut commands: Commands,
     ascii: Res<AsciiSheet>,
     characters: Res<CharacterSheet>,
+    encounter_tables: Res<EncounterTables>,
+    pending_encounter: Option<Res<PendingEncounter>>,
+    clock: Res<GameClock>,
+    weather: Res<CurrentWeather>,
 ) {
-    let enemy_type = match rand::random::<f32>() {
-        x if x < 0.5 => EnemyType::Bat,
-        _ => EnemyType::Ghost,
-    };
-    let stats = match enemy_type {
-        EnemyType::Bat => CombatStats{
-            health: 3,
-            max_health: 3,
-            attack: 2,
-            defense: 1,
-        },
-        EnemyType::Ghost => CombatStats{
-            health: 5,
-            max_health: 5,
-            attack: 3,
-            defense: 2,
-        },
-    };
-    let health_text = spawn_ascii_text(
-        &mut commands,
-        &ascii,
-        &format!("Health: {}", stats.health as usize),
-        Vec3::new(-4.5 * TILE_SIZE, 3.0 * TILE_SIZE, 100.0),
+    let zone = pending_encounter
+        .map(|pending| pending.zone.clone())
+        .unwrap_or_default();
+    let encounter = encounter_tables.roll(
+        &zone,
+        clock.time_of_day(),
+        weather.0,
+        &mut rand::thread_rng(),
+    );
+    let group_size = encounter.enemies.len();
+    let spacing = ENEMY_SPACING.min(
+        (2.0 * (RESOLUTION - ENEMY_MARGIN)) / (group_size.max(2) - 1) as f32,
     );
-    commands.entity(health_text).insert(CombatText);
 
-    let sprite = spawn_enemy_sprite(
+    for (i, (enemy_type, level)) in encounter.enemies.into_iter().enumerate() {
+        let stats = enemy_type.stats(level);
+        let health_text = spawn_ascii_text(
+            &mut commands,
+            &ascii,
+            &format!("Health: {}", stats.health as usize),
+            Vec3::new(-4.5 * TILE_SIZE, 3.0 * TILE_SIZE, 100.0),
+        );
+        commands.entity(health_text).insert(CombatText);
+
+        let x = (i as f32 - (group_size - 1) as f32 / 2.0) * spacing;
+        let sprite = spawn_enemy_sprite(
+            &mut commands,
+            &characters,
+            Vec3::new(x, 0.5, 100.0),
+            &enemy_type,
+        );
+        commands
+            .entity(sprite)
+            .insert(Enemy { enemy_type, level })
+            .insert(EffectiveStats::new(&stats, StatBonus::default()))
+            .insert(stats)
+            .insert(Name::new(enemy_type.name()))
+            .add_child(health_text);
+    }
+
+    let cursor = spawn_ascii_sprite(
         &mut commands,
-        &characters,
-        Vec3::new(0.0, 0.5, 100.0),
-        &enemy_type,
+        &ascii,
+        TARGET_CURSOR_GLYPH,
+        Color::RED,
+        Vec3::new(0.0, 0.0, 100.0),
+        Vec3::splat(1.0),
     );
     commands
-        .entity(sprite)
-        .insert(Enemy { enemy_type })
-        .insert(stats)
-        .insert(Name::new("Bat"))
-        .add_child(health_text);
+        .entity(cursor)
+        .insert(Visibility { is_visible: false })
+        .insert(Name::new("Target Cursor"))
+        .insert(TargetCursor);
 }
 
 fn despawn_enemy(
     mut commands: Commands,
-    enemy_query: Query<Entity, With<Enemy>>,
+    enemy_query: Query<Entity, Or<(With<Enemy>, With<TargetCursor>)>>,
 ) {
     for entity in enemy_query.iter() {
         commands.entity(entity).despawn_recursive();
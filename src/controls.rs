@@ -0,0 +1,222 @@
+use bevy::{prelude::*, render::camera::Camera2d};
+
+use crate::{
+    actions::{Action, ActionState, Binding, Bindings},
+    ascii::{
+        spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, AsciiSheet,
+        NineSliceIndicies,
+    },
+    player::Player,
+    GameState, CLEAR, TILE_SIZE,
+};
+
+/// Size of the controls screen in tiles.
+const MENU_WIDTH: usize = 34;
+const MENU_HEIGHT: usize = Action::ALL.len() + 6;
+/// Characters kept for the action name of every row.
+const NAME_WIDTH: usize = 11;
+/// Keys held for chords like `LControl+Q`, bound alone when released
+/// without pressing another key.
+const MODIFIERS: [KeyCode; 8] = [
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::LWin,
+    KeyCode::RWin,
+];
+
+/// Overlay listing every action with its bindings, where they are rebound.
+#[derive(Component)]
+pub struct ControlsMenu;
+
+#[derive(Default)]
+pub struct ControlsScreen {
+    pub open: bool,
+    /// Index into [`Action::ALL`].
+    selected: usize,
+    /// Waiting for the key or button to bind to the selected action.
+    listening: bool,
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsScreen>().add_system_set(
+            SystemSet::on_update(GameState::Overworld)
+                .with_system(controls_input)
+                .with_system(draw_controls.after(controls_input)),
+        );
+    }
+}
+
+fn controls_input(
+    mut screen: ResMut<ControlsScreen>,
+    mut bindings: ResMut<Bindings>,
+    mut player_query: Query<&mut Player>,
+    actions: Res<ActionState>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    let mut player = player_query.single_mut();
+    if !screen.open {
+        if player.active && actions.just_pressed(Action::Controls) {
+            screen.open = true;
+            player.active = false;
+        }
+        return;
+    }
+
+    // Rebinding reads raw input, any key or button not bound to Cancel will do
+    if screen.listening {
+        if actions.just_pressed(Action::Cancel) {
+            screen.listening = false;
+            return;
+        }
+        let action = Action::ALL[screen.selected];
+        match pressed_binding(&keyboard, &buttons) {
+            // A single key quits by accident too easily
+            Some(Binding::Key(_)) if action == Action::Quit => {}
+            Some(binding) => {
+                bindings.rebind(action, binding);
+                bindings.save();
+                screen.listening = false;
+            }
+            None => {}
+        }
+        return;
+    }
+
+    let count = Action::ALL.len();
+    if actions.any_just_pressed([Action::Cancel, Action::Controls]) {
+        screen.open = false;
+        player.active = true;
+    } else if actions.just_pressed(Action::MoveUp) {
+        screen.selected = (screen.selected + count - 1) % count;
+    } else if actions.just_pressed(Action::MoveDown) {
+        screen.selected = (screen.selected + 1) % count;
+    } else if actions.just_pressed(Action::Confirm) {
+        screen.listening = true;
+    }
+}
+
+/// What is being pressed to rebind an action: a key along with the modifiers
+/// held, a modifier released on its own or a gamepad button.
+fn pressed_binding(
+    keyboard: &Input<KeyCode>,
+    buttons: &Input<GamepadButton>,
+) -> Option<Binding> {
+    let key = keyboard
+        .get_just_pressed()
+        .find(|key| !MODIFIERS.contains(key));
+    if let Some(key) = key {
+        let mut keys: Vec<KeyCode> = MODIFIERS
+            .iter()
+            .filter(|modifier| keyboard.pressed(**modifier))
+            .copied()
+            .collect();
+        if keys.is_empty() {
+            return Some(Binding::Key(*key));
+        }
+        keys.push(*key);
+        return Some(Binding::Chord(keys));
+    }
+
+    let modifier = keyboard
+        .get_just_released()
+        .find(|key| MODIFIERS.contains(key));
+    if let Some(modifier) = modifier {
+        return Some(Binding::Key(*modifier));
+    }
+    buttons
+        .get_just_pressed()
+        .next()
+        .map(|button| Binding::Button(button.1))
+}
+
+/// Respawns the screen whenever the selection or the bindings change.
+fn draw_controls(
+    mut commands: Commands,
+    screen: Res<ControlsScreen>,
+    bindings: Res<Bindings>,
+    menu_query: Query<Entity, With<ControlsMenu>>,
+    camera_query: Query<&Transform, With<Camera2d>>,
+    ascii: Res<AsciiSheet>,
+    indices: Res<NineSliceIndicies>,
+) {
+    if !screen.is_changed() && !bindings.is_changed() {
+        return;
+    }
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+    if !screen.open {
+        return;
+    }
+
+    let selected = Action::ALL[screen.selected];
+    let (width, height) = (MENU_WIDTH as f32, MENU_HEIGHT as f32);
+    let nine_slice =
+        spawn_nine_slice(&mut commands, &ascii, &indices, width, height);
+    let background = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        0,
+        CLEAR,
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(width, height, 1.0),
+    );
+
+    let hint = if screen.listening && selected == Action::Quit {
+        "Hold modifier+key, Cancel: stop"
+    } else if screen.listening {
+        "Press key/button, Cancel: stop"
+    } else {
+        "Confirm: rebind  Cancel: close"
+    };
+    let mut lines = vec!["Controls".to_string(), String::new()];
+    lines.extend(Action::ALL.iter().enumerate().map(|(i, action)| {
+        let cursor = if i == screen.selected { '>' } else { ' ' };
+        let keys = bindings
+            .get(*action)
+            .iter()
+            .map(Binding::label)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let line = format!(
+            "{} {:width$} {}",
+            cursor,
+            format!("{:?}", action),
+            keys,
+            width = NAME_WIDTH
+        );
+        line.chars().take(MENU_WIDTH - 3).collect()
+    }));
+    lines.extend([String::new(), hint.to_string()]);
+
+    let left = (-width / 2.0 + 1.5) * TILE_SIZE;
+    let top = (height / 2.0 - 1.5) * TILE_SIZE;
+    let text: Vec<Entity> = lines
+        .iter()
+        .enumerate()
+        .map(|(row, line)| {
+            let translation =
+                Vec3::new(left, top - row as f32 * TILE_SIZE, 0.0);
+            spawn_ascii_text(&mut commands, &ascii, line, translation)
+        })
+        .collect();
+
+    let camera = camera_query.single().translation;
+    commands
+        .spawn()
+        .insert(Transform::from_xyz(camera.x, camera.y, 950.0))
+        .insert(GlobalTransform::default())
+        .insert(Name::new("Controls"))
+        .insert(ControlsMenu)
+        .add_child(background)
+        .add_child(nine_slice)
+        .push_children(&text);
+}
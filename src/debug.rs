@@ -1,7 +1,12 @@
 use bevy::prelude::*;
 use bevy_inspector_egui::{RegisterInspectable, WorldInspectorPlugin};
 
-use crate::{player::{Player, EncounterTracker}, combat::CombatStats};
+use crate::{
+    combat::CombatStats,
+    equipment::{EffectiveStats, Equipment},
+    inventory::{Inventory, ItemStack},
+    player::{EncounterTracker, Player},
+};
 
 pub struct DebugPlugin;
 
@@ -10,8 +15,12 @@ impl Plugin for DebugPlugin {
         if cfg!(debug_assertions) {
             app.add_plugin(WorldInspectorPlugin::new())
                 .register_type::<EncounterTracker>()
+                .register_type::<Equipment>()
+                .register_type::<Inventory>()
+                .register_type::<ItemStack>()
                 .register_inspectable::<Player>()
-                .register_inspectable::<CombatStats>();
+                .register_inspectable::<CombatStats>()
+                .register_inspectable::<EffectiveStats>();
         }
     }
 }
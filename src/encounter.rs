@@ -0,0 +1,161 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use serde::Deserialize;
+
+use crate::{clock::TimeOfDay, combat::EnemyType, weather::Weather};
+
+/// One possible fight in a zone, group size and level are inclusive ranges.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EncounterEntry {
+    pub enemy: EnemyType,
+    pub weight: u32,
+    pub group: (usize, usize),
+    pub level: (usize, usize),
+    /// Only rolled at this time of day, always when `None`.
+    #[serde(default)]
+    pub time: Option<TimeOfDay>,
+    /// Only rolled in this weather, always when `None`.
+    #[serde(default)]
+    pub weather: Option<Weather>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct EncounterTable {
+    /// Chance of a fight for every tile walked in the zone.
+    #[serde(default = "default_rate")]
+    pub rate: f32,
+    pub entries: Vec<EncounterEntry>,
+}
+
+/// Encounter tables keyed by the `encounter` zone id of tiles.
+pub struct EncounterTables(pub HashMap<String, EncounterTable>);
+
+/// Multipliers on top of the zone encounter rates, keyed by whatever applied
+/// them (an item, an ability...) so they can be taken off again.
+#[derive(Default)]
+pub struct EncounterRateModifiers(HashMap<String, f32>);
+
+/// Zone the player was in when the last fight started.
+pub struct PendingEncounter {
+    pub zone: String,
+}
+
+/// Enemies rolled for a single fight, as `(enemy, level)`.
+pub struct Encounter {
+    pub enemies: Vec<(EnemyType, usize)>,
+}
+
+pub struct EncounterPlugin;
+
+impl Plugin for EncounterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EncounterRateModifiers>()
+            .add_startup_system(load_encounter_tables);
+    }
+}
+
+fn default_rate() -> f32 {
+    1.0 / 16.0
+}
+
+impl EncounterEntry {
+    fn rolled_at(&self, time: TimeOfDay, weather: Weather) -> bool {
+        self.time.map_or(true, |only| only == time)
+            && self.weather.map_or(true, |only| only == weather)
+    }
+}
+
+impl EncounterTable {
+    pub fn roll(
+        &self,
+        time: TimeOfDay,
+        weather: Weather,
+        rng: &mut impl Rng,
+    ) -> Option<Encounter> {
+        let entries: Vec<&EncounterEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.rolled_at(time, weather))
+            .collect();
+        let weights = entries.iter().map(|entry| entry.weight);
+        let entry = entries[WeightedIndex::new(weights).ok()?.sample(rng)];
+
+        let group =
+            rng.gen_range(entry.group.0..=entry.group.1.max(entry.group.0));
+        let enemies = (0..group.max(1))
+            .map(|_| {
+                let level = rng.gen_range(
+                    entry.level.0..=entry.level.1.max(entry.level.0),
+                );
+                (entry.enemy, level.max(1))
+            })
+            .collect();
+
+        Some(Encounter { enemies })
+    }
+}
+
+impl EncounterTables {
+    /// Zero when no entry of the zone table is rolled at `time` in
+    /// `weather`.
+    pub fn rate(&self, zone: &str, time: TimeOfDay, weather: Weather) -> f32 {
+        let table = match self.0.get(zone) {
+            Some(table) => table,
+            None => return default_rate(),
+        };
+        if table
+            .entries
+            .iter()
+            .any(|entry| entry.rolled_at(time, weather))
+        {
+            table.rate
+        } else {
+            0.0
+        }
+    }
+
+    /// Rolls the table of `zone`, falling back to a single weak bat when the
+    /// zone has no usable table.
+    pub fn roll(
+        &self,
+        zone: &str,
+        time: TimeOfDay,
+        weather: Weather,
+        rng: &mut impl Rng,
+    ) -> Encounter {
+        self.0
+            .get(zone)
+            .and_then(|table| table.roll(time, weather, rng))
+            .unwrap_or_else(|| {
+                warn!("No encounter table for zone {}", zone);
+                Encounter {
+                    enemies: vec![(EnemyType::Bat, 1)],
+                }
+            })
+    }
+}
+
+impl EncounterRateModifiers {
+    /// Sets the multiplier of `source`, replacing its previous one.
+    pub fn insert(&mut self, source: impl Into<String>, multiplier: f32) {
+        self.0.insert(source.into(), multiplier);
+    }
+
+    pub fn remove(&mut self, source: &str) {
+        self.0.remove(source);
+    }
+
+    pub fn multiplier(&self) -> f32 {
+        self.0.values().product()
+    }
+}
+
+fn load_encounter_tables(mut commands: Commands) {
+    let text = fs::read_to_string("assets/encounters.ron")
+        .expect("No encounter tables found!");
+    let tables: HashMap<String, EncounterTable> =
+        ron::from_str(&text).expect("Invalid encounter tables!");
+    commands.insert_resource(EncounterTables(tables));
+}
@@ -0,0 +1,375 @@
+use bevy::{prelude::*, render::camera::Camera2d};
+use bevy_inspector_egui::Inspectable;
+use serde::Deserialize;
+
+use crate::{
+    actions::{Action, ActionState},
+    ascii::{
+        spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, AsciiSheet,
+        NineSliceIndicies,
+    },
+    combat::CombatStats,
+    inventory::{Inventory, ItemDefs, ItemKind},
+    player::Player,
+    GameState, CLEAR, TILE_SIZE,
+};
+
+/// Size of the equip screen in tiles.
+const MENU_WIDTH: usize = 34;
+const MENU_HEIGHT: usize = EquipSlot::ALL.len() + 12;
+/// Characters kept for the slot and stat names of every row.
+const NAME_WIDTH: usize = 10;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+    Accessory,
+}
+
+/// What an equipped item adds to the base stats.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatBonus {
+    #[serde(default)]
+    pub max_health: isize,
+    #[serde(default)]
+    pub attack: isize,
+    #[serde(default)]
+    pub defense: isize,
+}
+
+/// Item ids worn in every slot, kept out of the inventory while worn.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Equipment {
+    weapon: Option<String>,
+    armor: Option<String>,
+    accessory: Option<String>,
+}
+
+/// Base `CombatStats` plus equipment bonuses, what combat reads. Derived
+/// again whenever either changes, never set directly.
+#[derive(Component, Inspectable, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EffectiveStats {
+    pub max_health: isize,
+    pub attack: isize,
+    pub defense: isize,
+}
+
+/// Overlay for changing the equipment of the player.
+#[derive(Component)]
+pub struct EquipMenu;
+
+#[derive(Default)]
+pub struct EquipScreen {
+    pub open: bool,
+    /// Index into [`EquipSlot::ALL`].
+    selected: usize,
+}
+
+pub struct EquipmentPlugin;
+
+impl Plugin for EquipmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EquipScreen>()
+            .add_system(derive_stats.after(equip_input))
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(equip_input)
+                    .with_system(draw_equipment.after(derive_stats)),
+            );
+    }
+}
+
+impl EquipSlot {
+    pub const ALL: [EquipSlot; 3] =
+        [EquipSlot::Weapon, EquipSlot::Armor, EquipSlot::Accessory];
+}
+
+impl Equipment {
+    pub fn get(&self, slot: EquipSlot) -> Option<&str> {
+        match slot {
+            EquipSlot::Weapon => self.weapon.as_deref(),
+            EquipSlot::Armor => self.armor.as_deref(),
+            EquipSlot::Accessory => self.accessory.as_deref(),
+        }
+    }
+
+    fn slot_mut(&mut self, slot: EquipSlot) -> &mut Option<String> {
+        match slot {
+            EquipSlot::Weapon => &mut self.weapon,
+            EquipSlot::Armor => &mut self.armor,
+            EquipSlot::Accessory => &mut self.accessory,
+        }
+    }
+
+    /// Wears `item` from the inventory in `slot`, or empties the slot when
+    /// `None`, putting the old item back in the inventory. Nothing changes
+    /// when the item doesn't go in that slot or the old one doesn't fit.
+    pub fn equip(
+        &mut self,
+        slot: EquipSlot,
+        item: Option<&str>,
+        inventory: &mut Inventory,
+        defs: &ItemDefs,
+    ) -> bool {
+        if let Some(item) = item {
+            if equip_slot(defs, item) != Some(slot)
+                || !inventory.remove(item, 1)
+            {
+                return false;
+            }
+        }
+        if let Some(old) = self.get(slot) {
+            if inventory.add(defs, old, 1) > 0 {
+                if let Some(item) = item {
+                    inventory.add(defs, item, 1);
+                }
+                return false;
+            }
+        }
+        *self.slot_mut(slot) = item.map(str::to_string);
+        true
+    }
+
+    /// Sum of the bonuses of everything worn.
+    pub fn bonus(&self, defs: &ItemDefs) -> StatBonus {
+        EquipSlot::ALL
+            .iter()
+            .filter_map(|slot| self.get(*slot))
+            .filter_map(|item| match defs.get(item)?.kind {
+                ItemKind::Equipment { bonus, .. } => Some(bonus),
+                _ => None,
+            })
+            .fold(StatBonus::default(), |total, bonus| StatBonus {
+                max_health: total.max_health + bonus.max_health,
+                attack: total.attack + bonus.attack,
+                defense: total.defense + bonus.defense,
+            })
+    }
+}
+
+impl EffectiveStats {
+    pub fn new(base: &CombatStats, bonus: StatBonus) -> Self {
+        EffectiveStats {
+            max_health: (base.max_health + bonus.max_health).max(1),
+            attack: (base.attack + bonus.attack).max(0),
+            defense: (base.defense + bonus.defense).max(0),
+        }
+    }
+}
+
+/// Slot `item` is worn in, `None` when it isn't equipment.
+pub fn equip_slot(defs: &ItemDefs, item: &str) -> Option<EquipSlot> {
+    match defs.get(item)?.kind {
+        ItemKind::Equipment { slot, .. } => Some(slot),
+        _ => None,
+    }
+}
+
+/// Keeps the effective stats of every fighter in sync with its base stats
+/// and equipment, fighters without equipment just copy their base stats.
+/// Systems reading `EffectiveStats` run after it, so they never see stats
+/// from before an equipment change.
+pub fn derive_stats(
+    mut commands: Commands,
+    mut stats_query: Query<
+        (
+            Entity,
+            &mut CombatStats,
+            Option<&Equipment>,
+            Option<&mut EffectiveStats>,
+        ),
+        Or<(
+            Changed<CombatStats>,
+            Changed<Equipment>,
+            Without<EffectiveStats>,
+        )>,
+    >,
+    defs: Res<ItemDefs>,
+) {
+    for (entity, mut base, equipment, effective) in stats_query.iter_mut() {
+        let bonus = equipment.map_or_else(StatBonus::default, |equipment| {
+            equipment.bonus(&defs)
+        });
+        let derived = EffectiveStats::new(&base, bonus);
+        // Taking off health bonuses can leave more health than the maximum
+        if base.health > derived.max_health {
+            base.health = derived.max_health;
+        }
+        match effective {
+            Some(mut effective) if *effective != derived => {
+                *effective = derived
+            }
+            Some(_) => {}
+            None => {
+                commands.entity(entity).insert(derived);
+            }
+        }
+    }
+}
+
+fn equip_input(
+    mut screen: ResMut<EquipScreen>,
+    mut player_query: Query<(&mut Player, &mut Equipment, &mut Inventory)>,
+    actions: Res<ActionState>,
+    defs: Res<ItemDefs>,
+) {
+    let (mut player, mut equipment, mut inventory) = player_query.single_mut();
+    if !screen.open {
+        if player.active && actions.just_pressed(Action::Equipment) {
+            screen.open = true;
+            player.active = false;
+        }
+        return;
+    }
+
+    let count = EquipSlot::ALL.len();
+    if actions.any_just_pressed([Action::Cancel, Action::Equipment]) {
+        screen.open = false;
+        player.active = true;
+    } else if actions.just_pressed(Action::MoveUp) {
+        screen.selected = (screen.selected + count - 1) % count;
+    } else if actions.just_pressed(Action::MoveDown) {
+        screen.selected = (screen.selected + 1) % count;
+    } else if actions.any_just_pressed([Action::MoveLeft, Action::MoveRight]) {
+        // Cycles through nothing and every item for the slot, carried or
+        // worn, in a fixed order so it doesn't shift as items move
+        let slot = EquipSlot::ALL[screen.selected];
+        let worn = equipment.get(slot).map(str::to_string);
+        let mut options: Vec<Option<String>> = inventory
+            .stacks()
+            .iter()
+            .map(|stack| &stack.item)
+            .filter(|item| equip_slot(&defs, item) == Some(slot))
+            .cloned()
+            .chain(worn.clone())
+            .map(Some)
+            .chain([None])
+            .collect();
+        options.sort();
+        options.dedup();
+
+        let current = options
+            .iter()
+            .position(|item| *item == worn)
+            .unwrap_or_default();
+        let next = if actions.just_pressed(Action::MoveLeft) {
+            (current + options.len() - 1) % options.len()
+        } else {
+            (current + 1) % options.len()
+        };
+        equipment.equip(slot, options[next].as_deref(), &mut inventory, &defs);
+    }
+}
+
+/// Respawns the screen whenever the selection or the equipment change.
+fn draw_equipment(
+    mut commands: Commands,
+    screen: Res<EquipScreen>,
+    player_query: Query<
+        (&CombatStats, &EffectiveStats, &Equipment),
+        With<Player>,
+    >,
+    changed_query: Query<
+        (),
+        (
+            With<Player>,
+            Or<(Changed<Equipment>, Changed<EffectiveStats>)>,
+        ),
+    >,
+    menu_query: Query<Entity, With<EquipMenu>>,
+    camera_query: Query<&Transform, With<Camera2d>>,
+    defs: Res<ItemDefs>,
+    ascii: Res<AsciiSheet>,
+    indices: Res<NineSliceIndicies>,
+) {
+    if !screen.is_changed() && changed_query.is_empty() {
+        return;
+    }
+    for menu in menu_query.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+    if !screen.open {
+        return;
+    }
+
+    let (base, effective, equipment) = player_query.single();
+    let (width, height) = (MENU_WIDTH as f32, MENU_HEIGHT as f32);
+    let nine_slice =
+        spawn_nine_slice(&mut commands, &ascii, &indices, width, height);
+    let background = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        0,
+        CLEAR,
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(width, height, 1.0),
+    );
+
+    let mut lines = vec!["Equipment".to_string(), String::new()];
+    lines.extend(EquipSlot::ALL.iter().enumerate().map(|(i, slot)| {
+        let cursor = if i == screen.selected { '>' } else { ' ' };
+        let item = equipment.get(*slot).map_or("-", |item| defs.name(item));
+        format!(
+            "{} {:width$} < {} >",
+            cursor,
+            format!("{:?}", slot),
+            item,
+            width = NAME_WIDTH
+        )
+    }));
+    lines.extend([
+        String::new(),
+        format!(
+            "  {:width$}{:>4}{:>7}",
+            "",
+            "base",
+            "total",
+            width = NAME_WIDTH
+        ),
+    ]);
+    let stats = [
+        ("Health", base.max_health, effective.max_health),
+        ("Attack", base.attack, effective.attack),
+        ("Defense", base.defense, effective.defense),
+    ];
+    lines.extend(stats.iter().map(|(name, base, total)| {
+        format!(
+            "  {:width$}{:>4}{:>7}",
+            name,
+            base,
+            total,
+            width = NAME_WIDTH
+        )
+    }));
+    lines.extend([
+        String::new(),
+        "Left/Right: change".to_string(),
+        "Cancel: close".to_string(),
+    ]);
+
+    let left = (-width / 2.0 + 1.5) * TILE_SIZE;
+    let top = (height / 2.0 - 1.5) * TILE_SIZE;
+    let text: Vec<Entity> = lines
+        .iter()
+        .map(|line| line.chars().take(MENU_WIDTH - 3).collect::<String>())
+        .enumerate()
+        .map(|(row, line)| {
+            let translation =
+                Vec3::new(left, top - row as f32 * TILE_SIZE, 0.0);
+            spawn_ascii_text(&mut commands, &ascii, &line, translation)
+        })
+        .collect();
+
+    let camera = camera_query.single().translation;
+    commands
+        .spawn()
+        .insert(Transform::from_xyz(camera.x, camera.y, 950.0))
+        .insert(GlobalTransform::default())
+        .insert(Name::new("Equipment"))
+        .insert(EquipMenu)
+        .add_child(background)
+        .add_child(nine_slice)
+        .push_children(&text);
+}
@@ -1,12 +1,24 @@
 use bevy::prelude::*;
 
-use crate::{ascii::AsciiSheet, GameState};
+use crate::{
+    ascii::AsciiSheet,
+    tilemap::{Warp, WarpEvent},
+    GameState,
+};
+
+/// What happens once the screen is fully faded out.
+#[derive(Clone)]
+enum FadeAction {
+    PushState(GameState),
+    PopState,
+    Warp(Warp),
+}
 
 #[derive(Component)]
 pub struct ScreenFade {
     alpha: f32,
     sent: bool,
-    next_state: Option<GameState>,
+    action: FadeAction,
     timer: Timer,
 }
 
@@ -39,6 +51,7 @@ fn fadeout(
     mut commands: Commands,
     mut fade_query: Query<(Entity, &mut ScreenFade, &mut TextureAtlasSprite)>,
     mut state: ResMut<State<GameState>>,
+    mut warp_events: EventWriter<WarpEvent>,
     time: Res<Time>,
 ) {
     for (entity, mut fade, mut sprite) in fade_query.iter_mut() {
@@ -51,10 +64,14 @@ fn fadeout(
         sprite.color.set_a(fade.alpha);
 
         if fade.timer.percent() > 0.5 && !fade.sent {
-            if let Some(next_state) = fade.next_state {
-                state.push(next_state);
-            } else {
-                state.pop();
+            match fade.action.clone() {
+                FadeAction::PushState(next_state) => {
+                    state.push(next_state);
+                }
+                FadeAction::PopState => {
+                    state.pop();
+                }
+                FadeAction::Warp(warp) => warp_events.send(WarpEvent(warp)),
             }
             fade.sent = true;
         }
@@ -69,6 +86,27 @@ pub fn create_fadeout(
     commands: &mut Commands,
     next_state: Option<GameState>,
     ascii: &Res<AsciiSheet>,
+) {
+    let action = match next_state {
+        Some(next_state) => FadeAction::PushState(next_state),
+        None => FadeAction::PopState,
+    };
+    spawn_fadeout(commands, action, ascii);
+}
+
+/// Fades out, swaps the current map for the warp target, then fades back in.
+pub fn create_warp_fadeout(
+    commands: &mut Commands,
+    warp: Warp,
+    ascii: &Res<AsciiSheet>,
+) {
+    spawn_fadeout(commands, FadeAction::Warp(warp), ascii);
+}
+
+fn spawn_fadeout(
+    commands: &mut Commands,
+    action: FadeAction,
+    ascii: &Res<AsciiSheet>,
 ) {
     let mut sprite = TextureAtlasSprite::new(0);
     sprite.color = Color::rgba(0.1, 0.1, 0.15, 0.0);
@@ -86,7 +124,7 @@ pub fn create_fadeout(
         .insert(ScreenFade {
             alpha: 0.0,
             sent: false,
-            next_state,
+            action,
             timer: Timer::from_seconds(1.0, false),
         })
         .insert(Name::new("Fadeout"));
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    map::MapData,
+    player::Player,
+    tilemap::{CurrentMap, MapKey, TileGrid},
+    weather::CurrentWeather,
+    GameState,
+};
+
+/// How much of their color explored tiles keep while out of sight.
+const EXPLORED_BRIGHTNESS: f32 = 0.35;
+
+/// Tiles the player has seen so far, per map so revisiting a map keeps
+/// what was already explored.
+#[derive(Default)]
+pub struct FieldOfView {
+    explored: HashMap<MapKey, Vec<bool>>,
+    /// Player tile the visible tiles were last computed from.
+    origin: Option<(i32, i32)>,
+}
+
+pub struct FovPlugin;
+
+impl Plugin for FovPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FieldOfView>().add_system_set(
+            SystemSet::on_update(GameState::Overworld).with_system(update_fov),
+        );
+    }
+}
+
+fn update_fov(
+    mut fov: ResMut<FieldOfView>,
+    mut grid: ResMut<TileGrid>,
+    current: Option<Res<CurrentMap>>,
+    maps: Res<Assets<MapData>>,
+    weather: Res<CurrentWeather>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let current = match current {
+        Some(current) if current.spawned => current,
+        _ => return,
+    };
+    let map_radius = maps
+        .get(&current.handle)
+        .and_then(|map| map.header.fog_of_war);
+    let radius = match (map_radius, weather.0.sight_radius()) {
+        (Some(map_radius), Some(weather_radius)) => {
+            map_radius.min(weather_radius)
+        }
+        (Some(radius), None) | (None, Some(radius)) => radius,
+        (None, None) => {
+            // The fog lifted on a map that is otherwise fully visible
+            if fov.origin.take().is_some() {
+                reveal_all(&mut grid);
+            }
+            return;
+        }
+    };
+    let origin = TileGrid::tile_coords(player_query.single().translation);
+    if fov.origin == Some(origin) && !grid.is_changed() && !weather.is_changed()
+    {
+        return;
+    }
+    fov.origin = Some(origin);
+
+    let tile_count = grid.width * grid.height;
+    let explored = fov.explored.entry(current.key()).or_default();
+    // Hot reloaded maps can change size, forget them then
+    if explored.len() != tile_count {
+        *explored = vec![false; tile_count];
+    }
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let shown = match grid.get(x as i32, y as i32) {
+                Some(tile) => tile.brightness,
+                None => continue,
+            };
+            let visible = in_sight(&grid, origin, (x as i32, y as i32), radius);
+            let seen = &mut explored[y * grid.width + x];
+            *seen |= visible;
+
+            let brightness = if visible {
+                1.0
+            } else if *seen {
+                EXPLORED_BRIGHTNESS
+            } else {
+                0.0
+            };
+            // Changing the grid redraws the chunk and runs this again, so
+            // only touch tiles that actually change
+            if brightness != shown {
+                if let Some(tile) = grid.get_mut(x as i32, y as i32) {
+                    tile.brightness = brightness;
+                }
+            }
+        }
+    }
+}
+
+fn reveal_all(grid: &mut TileGrid) {
+    let hidden: Vec<(i32, i32)> = grid
+        .iter()
+        .filter(|(_, tile)| tile.brightness != 1.0)
+        .map(|(position, _)| position)
+        .collect();
+    for (x, y) in hidden {
+        if let Some(tile) = grid.get_mut(x, y) {
+            tile.brightness = 1.0;
+        }
+    }
+}
+
+/// Whether `target` is within `radius` of `origin` with no collider on the
+/// line between them, colliders themselves are visible.
+fn in_sight(
+    grid: &TileGrid,
+    origin: (i32, i32),
+    target: (i32, i32),
+    radius: i32,
+) -> bool {
+    let (dx, dy) = (target.0 - origin.0, target.1 - origin.1);
+    if dx * dx + dy * dy > radius * radius {
+        return false;
+    }
+
+    let steps = dx.abs().max(dy.abs());
+    (1..steps).all(|step| {
+        let t = step as f32 / steps as f32;
+        let x = origin.0 + (dx as f32 * t).round() as i32;
+        let y = origin.1 + (dy as f32 * t).round() as i32;
+        grid.get(x, y).map_or(true, |tile| !tile.collider)
+    })
+}
@@ -0,0 +1,408 @@
+use std::collections::{HashMap, VecDeque};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+
+use crate::{
+    map::{MapData, MapEntity, MapHeader, TileDef},
+    npc::Npc,
+    tilemap::Warp,
+};
+
+const WALL: char = '#';
+const FLOOR: char = '.';
+const GRASS: char = '~';
+const NPC: char = '@';
+const EXIT: char = '<';
+const ENTRANCE: char = 'a';
+const SIGHT_RADIUS: i32 = 7;
+
+/// Spawn point every generated map places next to its exit.
+pub const ENTRANCE_SPAWN_POINT: &str = "entrance";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    RoomsAndCorridors,
+    CellularAutomata,
+}
+
+/// How to build a generated map, as listed under `generated` in the map
+/// registry.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GeneratorConfig {
+    pub algorithm: Algorithm,
+    pub width: usize,
+    pub height: usize,
+    /// Fixed seed, a new dungeon is rolled on every visit without one.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Where the exit tile next to the entrance leads.
+    pub exit: Warp,
+}
+
+/// Generates a map with the same legend driven representation as map files,
+/// the same seed always produces the same map, on every platform and rand
+/// release.
+pub fn generate(config: &GeneratorConfig, seed: u64) -> MapData {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let width = config.width.max(8);
+    let height = config.height.max(8);
+
+    let rows = match config.algorithm {
+        Algorithm::RoomsAndCorridors => {
+            rooms_and_corridors(&mut rng, width, height)
+        }
+        Algorithm::CellularAutomata => {
+            cellular_automata(&mut rng, width, height)
+        }
+    };
+
+    MapData {
+        header: MapHeader {
+            name: format!("Random Dungeon {}", seed),
+            legend: legend(&config.exit),
+            fog_of_war: Some(SIGHT_RADIUS),
+            indoors: true,
+            weather: None,
+            movement: None,
+        },
+        rows,
+        layout_line: None,
+    }
+}
+
+fn legend(exit: &Warp) -> HashMap<char, TileDef> {
+    let mut legend = HashMap::new();
+    legend.insert(
+        WALL,
+        TileDef {
+            color: (0.4, 0.4, 0.5),
+            collider: true,
+            ..TileDef::default()
+        },
+    );
+    legend.insert(
+        FLOOR,
+        TileDef {
+            color: (0.6, 0.6, 0.6),
+            ..TileDef::default()
+        },
+    );
+    legend.insert(
+        GRASS,
+        TileDef {
+            index: Some(',' as usize),
+            color: (0.5, 0.3, 0.6),
+            encounter: Some("depths".to_string()),
+            ..TileDef::default()
+        },
+    );
+    legend.insert(
+        NPC,
+        TileDef {
+            color: (0.5, 0.5, 0.2),
+            collider: true,
+            spawn: Some(MapEntity::Npc(Npc::Healer)),
+            ..TileDef::default()
+        },
+    );
+    legend.insert(
+        EXIT,
+        TileDef {
+            color: (0.6, 0.4, 0.8),
+            warp: Some(exit.clone()),
+            ..TileDef::default()
+        },
+    );
+    legend.insert(
+        ENTRANCE,
+        TileDef {
+            index: Some(FLOOR as usize),
+            color: (0.6, 0.6, 0.6),
+            spawn_point: Some(ENTRANCE_SPAWN_POINT.to_string()),
+            ..TileDef::default()
+        },
+    );
+    legend
+}
+
+struct Room {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Room {
+    fn center(&self) -> (usize, usize) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    /// Overlap test that keeps at least one wall between rooms.
+    fn intersects(&self, other: &Room) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+}
+
+fn rooms_and_corridors(
+    rng: &mut ChaCha8Rng,
+    width: usize,
+    height: usize,
+) -> Vec<Vec<char>> {
+    let mut rows = vec![vec![WALL; width]; height];
+    let mut rooms: Vec<Room> = Vec::new();
+
+    for _ in 0..40 {
+        let room_width = rng.gen_range(4..=10.min(width - 2));
+        let room_height = rng.gen_range(3..=7.min(height - 2));
+        let room = Room {
+            x: rng.gen_range(1..=width - 1 - room_width),
+            y: rng.gen_range(1..=height - 1 - room_height),
+            width: room_width,
+            height: room_height,
+        };
+        if rooms.iter().any(|other| room.intersects(other)) {
+            continue;
+        }
+
+        let tile = if !rooms.is_empty() && rng.gen_bool(0.4) {
+            GRASS
+        } else {
+            FLOOR
+        };
+        for row in rows.iter_mut().skip(room.y).take(room.height) {
+            for glyph in row.iter_mut().skip(room.x).take(room.width) {
+                *glyph = tile;
+            }
+        }
+
+        if let Some(previous) = rooms.last() {
+            carve_corridor(rng, &mut rows, previous.center(), room.center());
+        }
+        rooms.push(room);
+    }
+
+    // The first room always fits since the map is at least 8x8
+    let (x, y) = rooms[0].center();
+    rows[y][x - 1] = EXIT;
+    rows[y][x] = ENTRANCE;
+    if rooms.len() > 1 {
+        let (x, y) = rooms[rooms.len() - 1].center();
+        rows[y][x] = NPC;
+    }
+
+    rows
+}
+
+fn carve_corridor(
+    rng: &mut ChaCha8Rng,
+    rows: &mut [Vec<char>],
+    (x1, y1): (usize, usize),
+    (x2, y2): (usize, usize),
+) {
+    let mut carve = |x: usize, y: usize| {
+        if rows[y][x] == WALL {
+            rows[y][x] = FLOOR;
+        }
+    };
+
+    if rng.gen_bool(0.5) {
+        (x1.min(x2)..=x1.max(x2)).for_each(|x| carve(x, y1));
+        (y1.min(y2)..=y1.max(y2)).for_each(|y| carve(x2, y));
+    } else {
+        (y1.min(y2)..=y1.max(y2)).for_each(|y| carve(x1, y));
+        (x1.min(x2)..=x1.max(x2)).for_each(|x| carve(x, y2));
+    }
+}
+
+fn cellular_automata(
+    rng: &mut ChaCha8Rng,
+    width: usize,
+    height: usize,
+) -> Vec<Vec<char>> {
+    let mut walls: Vec<Vec<bool>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    x == 0
+                        || y == 0
+                        || x == width - 1
+                        || y == height - 1
+                        || rng.gen_bool(0.45)
+                })
+                .collect()
+        })
+        .collect();
+    for _ in 0..5 {
+        walls = smooth(&walls, |neighbours, wall| {
+            neighbours >= 5 || (wall && neighbours >= 4)
+        });
+    }
+    keep_largest_cave(&mut walls);
+
+    let mut grass: Vec<Vec<bool>> = (0..height)
+        .map(|_| (0..width).map(|_| rng.gen_bool(0.5)).collect())
+        .collect();
+    for _ in 0..3 {
+        grass = smooth(&grass, |neighbours, _| neighbours >= 5);
+    }
+
+    let mut rows: Vec<Vec<char>> = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| match (walls[y][x], grass[y][x]) {
+                    (true, _) => WALL,
+                    (false, true) => GRASS,
+                    (false, false) => FLOOR,
+                })
+                .collect()
+        })
+        .collect();
+
+    let floor = |rows: &[Vec<char>], x: usize, y: usize| rows[y][x] != WALL;
+    let entrance = (1..height - 1)
+        .flat_map(|y| (1..width - 2).map(move |x| (x, y)))
+        .find(|&(x, y)| floor(&rows, x, y) && floor(&rows, x + 1, y));
+    // Unlucky seeds can fill the whole map, fall back to rooms then
+    let (x, y) = match entrance {
+        Some(entrance) => entrance,
+        None => return rooms_and_corridors(rng, width, height),
+    };
+    rows[y][x] = EXIT;
+    rows[y][x + 1] = ENTRANCE;
+
+    // Only place the npc in open areas so it never blocks a passage
+    let npc = (1..height - 1)
+        .rev()
+        .flat_map(|y| (1..width - 1).rev().map(move |x| (x, y)))
+        .find(|&(x, y)| {
+            (y - 1..=y + 1).all(|ny| {
+                (x - 1..=x + 1)
+                    .all(|nx| rows[ny][nx] == FLOOR || rows[ny][nx] == GRASS)
+            })
+        });
+    if let Some((x, y)) = npc {
+        rows[y][x] = NPC;
+    }
+
+    rows
+}
+
+/// One cellular automata step, `rule` gets the number of set neighbours
+/// (out of bounds counts as set) and the current value.
+fn smooth(
+    cells: &[Vec<bool>],
+    rule: impl Fn(usize, bool) -> bool,
+) -> Vec<Vec<bool>> {
+    let height = cells.len() as isize;
+    let width = cells[0].len() as isize;
+
+    (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                        return cells[y as usize][x as usize];
+                    }
+                    let neighbours = (y - 1..=y + 1)
+                        .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                        .filter(|&(nx, ny)| (nx, ny) != (x, y))
+                        .filter(|&(nx, ny)| cells[ny as usize][nx as usize])
+                        .count();
+                    rule(neighbours, cells[y as usize][x as usize])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Fills every cave except the biggest one, so the whole map is reachable.
+fn keep_largest_cave(walls: &mut [Vec<bool>]) {
+    let height = walls.len();
+    let width = walls[0].len();
+    let mut cave_of = vec![vec![None; width]; height];
+    let mut sizes = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            if walls[y][x] || cave_of[y][x].is_some() {
+                continue;
+            }
+            let cave = sizes.len();
+            let mut size = 0;
+            let mut queue = VecDeque::from([(x, y)]);
+            cave_of[y][x] = Some(cave);
+            while let Some((cx, cy)) = queue.pop_front() {
+                size += 1;
+                for (nx, ny) in
+                    [(cx - 1, cy), (cx + 1, cy), (cx, cy - 1), (cx, cy + 1)]
+                {
+                    if !walls[ny][nx] && cave_of[ny][nx].is_none() {
+                        cave_of[ny][nx] = Some(cave);
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+    }
+
+    let largest = (0..sizes.len()).max_by_key(|&cave| sizes[cave]);
+    for y in 0..height {
+        for x in 0..width {
+            if cave_of[y][x] != largest {
+                walls[y][x] = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(algorithm: Algorithm) -> GeneratorConfig {
+        GeneratorConfig {
+            algorithm,
+            width: 40,
+            height: 25,
+            seed: None,
+            exit: Warp {
+                map: "overworld".to_string(),
+                spawn: "from_dungeon".to_string(),
+            },
+        }
+    }
+
+    /// FNV-1a of the layout, stable unlike the std hasher.
+    fn layout_hash(map: &MapData) -> u64 {
+        map.rows
+            .iter()
+            .flatten()
+            .fold(0xcbf29ce484222325, |hash, glyph| {
+                (hash ^ *glyph as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn same_seed_same_map() {
+        for algorithm in
+            [Algorithm::RoomsAndCorridors, Algorithm::CellularAutomata]
+        {
+            let config = config(algorithm);
+            assert_eq!(generate(&config, 7).rows, generate(&config, 7).rows);
+        }
+    }
+
+    #[test]
+    fn fixed_seed_fixed_layout() {
+        let rooms = generate(&config(Algorithm::RoomsAndCorridors), 1234);
+        let caves = generate(&config(Algorithm::CellularAutomata), 1234);
+        assert_eq!(layout_hash(&rooms), 13463066628232326264);
+        assert_eq!(layout_hash(&caves), 4268520602525945341);
+    }
+}
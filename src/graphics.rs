@@ -19,6 +19,18 @@ pub enum FacingDirection {
     Right,
 }
 
+impl FacingDirection {
+    /// Step to the faced tile, tile rows grow downwards.
+    pub fn tile_offset(&self) -> (i32, i32) {
+        match self {
+            FacingDirection::Up => (0, -1),
+            FacingDirection::Down => (0, 1),
+            FacingDirection::Left => (-1, 0),
+            FacingDirection::Right => (1, 0),
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct PlayerGraphics {
     pub facing: FacingDirection,
@@ -109,6 +121,31 @@ impl GraphicsPlugin {
     }
 }
 
+/// Darkens `color`, fully dark colors become transparent.
+pub fn shade(color: Color, brightness: f32) -> Color {
+    if brightness <= 0.0 {
+        return Color::NONE;
+    }
+    Color::rgb(
+        color.r() * brightness,
+        color.g() * brightness,
+        color.b() * brightness,
+    )
+}
+
+/// Multiplies `color` by the daylight `tint`, `light` from 0 to 1 blends in
+/// how much nearby light sources keep of the original color.
+pub fn lit(color: Color, tint: Color, light: f32) -> Color {
+    let light = light.clamp(0.0, 1.0);
+    let channel = |value: f32, tint: f32| value * (tint + (1.0 - tint) * light);
+    Color::rgba(
+        channel(color.r(), tint.r()),
+        channel(color.g(), tint.g()),
+        channel(color.b(), tint.b()),
+        color.a(),
+    )
+}
+
 pub fn spawn_enemy_sprite(
     commands: &mut Commands,
     characters: &CharacterSheet,
@@ -0,0 +1,277 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use bevy::{prelude::*, render::camera::Camera2d};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{Action, ActionState},
+    ascii::{AsciiSheet, NineSliceIndicies},
+    graphics::{FacingDirection, PlayerGraphics},
+    inventory::{Inventory, ItemDefs},
+    map::TileDef,
+    npc::{clear_speech, spawn_textbox},
+    player::{wall_collision_check, Player},
+    tilemap::{tile_translation, CurrentMap, GridTile, MapKey, TileGrid},
+    GameState, TILE_SIZE,
+};
+
+const OPENED_CHEST_INDEX: usize = '_' as usize;
+const OPEN_DOOR_INDEX: usize = '\'' as usize;
+
+/// Map objects the player can use with the interact key.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub enum Interactable {
+    /// Gives `count` of `item` the first time it is opened.
+    Chest {
+        item: String,
+        #[serde(default = "default_count")]
+        count: usize,
+    },
+    Sign(String),
+    /// Opens for a player carrying `key`, using it up.
+    Door {
+        key: String,
+    },
+    /// Flips the collider of every tile toggled by this switch id.
+    Switch(String),
+}
+
+/// Tile whose collider a switch flips, drawn with `index` while flipped.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Toggle {
+    pub switch: String,
+    pub index: usize,
+}
+
+/// What the player changed on a map, restored when the map is spawned again.
+#[derive(Default)]
+pub struct MapState {
+    /// Opened chests and doors.
+    pub used: HashSet<(usize, usize)>,
+    /// Switches that are currently flipped.
+    pub switches: HashSet<String>,
+}
+
+#[derive(Default)]
+pub struct MapChanges(pub HashMap<MapKey, MapState>);
+
+pub struct InteractPlugin;
+
+impl Plugin for InteractPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapChanges>().add_system_set(
+            SystemSet::on_update(GameState::Overworld)
+                .with_system(interact.before(clear_speech)),
+        );
+    }
+}
+
+fn default_count() -> usize {
+    1
+}
+
+/// Atlas index, collider and toggle of a tile after the changes in `state`.
+/// A flipped toggle holds the index to go back to.
+pub fn restored_tile(
+    def: &TileDef,
+    index: usize,
+    position: (usize, usize),
+    state: Option<&MapState>,
+) -> (usize, bool, Option<Toggle>) {
+    let state = match state {
+        Some(state) => state,
+        None => return (index, def.collider, def.toggle.clone()),
+    };
+
+    let used = state.used.contains(&position);
+    let (mut index, mut collider) = match &def.interact {
+        Some(Interactable::Chest { .. }) if used => {
+            (OPENED_CHEST_INDEX, def.collider)
+        }
+        Some(Interactable::Door { .. }) if used => (OPEN_DOOR_INDEX, false),
+        _ => (index, def.collider),
+    };
+    let mut toggle = def.toggle.clone();
+    if let Some(toggle) = toggle.as_mut() {
+        if state.switches.contains(&toggle.switch) {
+            std::mem::swap(&mut index, &mut toggle.index);
+            collider = !collider;
+        }
+    }
+    (index, collider, toggle)
+}
+
+fn interact(
+    mut commands: Commands,
+    mut player_query: Query<(
+        &mut Player,
+        &mut Inventory,
+        &Transform,
+        &PlayerGraphics,
+    )>,
+    camera_query: Query<&Transform, With<Camera2d>>,
+    mut grid: ResMut<TileGrid>,
+    mut changes: ResMut<MapChanges>,
+    current: Option<Res<CurrentMap>>,
+    actions: Res<ActionState>,
+    items: Res<ItemDefs>,
+    ascii: Res<AsciiSheet>,
+    indices: Res<NineSliceIndicies>,
+) {
+    let (mut player, mut inventory, transform, graphics) =
+        player_query.single_mut();
+    let current = match current {
+        Some(current) => current,
+        None => return,
+    };
+    if !player.active || !actions.just_pressed(Action::Interact) {
+        return;
+    }
+
+    let target = interact_target(&grid, transform, &graphics.facing)
+        .filter(|(_, target)| target.npc.is_none())
+        .and_then(|(tile, target)| Some((tile, target.interact.clone()?)));
+    let (tile, interactable) = match target {
+        Some(target) => target,
+        None => return,
+    };
+    let state = changes.0.entry(current.key()).or_default();
+    let position = (tile.0 as usize, tile.1 as usize);
+
+    let message = match interactable {
+        Interactable::Chest { .. } if state.used.contains(&position) => {
+            Some("The chest is empty.".to_string())
+        }
+        // Stays closed until everything in it fits
+        Interactable::Chest { item, count }
+            if inventory.room_for(&items, &item) < count =>
+        {
+            Some(format!("No room for {} x{}.", items.name(&item), count))
+        }
+        Interactable::Chest { item, count } => {
+            state.used.insert(position);
+            inventory.add(&items, &item, count);
+            set_tile(&mut grid, tile, OPENED_CHEST_INDEX, None);
+            Some(format!("Found {} x{}!", items.name(&item), count))
+        }
+        Interactable::Sign(text) => Some(text),
+        Interactable::Door { .. } if state.used.contains(&position) => None,
+        Interactable::Door { key } => {
+            if inventory.remove(&key, 1) {
+                state.used.insert(position);
+                set_tile(&mut grid, tile, OPEN_DOOR_INDEX, Some(false));
+                None
+            } else {
+                Some(format!(
+                    "The door is locked, it needs a {}.",
+                    items.name(&key)
+                ))
+            }
+        }
+        Interactable::Switch(switch) => {
+            if flip_switch(&mut grid, &switch, transform.translation) {
+                if !state.switches.remove(&switch) {
+                    state.switches.insert(switch.clone());
+                }
+                None
+            } else {
+                Some("Something is in the way.".to_string())
+            }
+        }
+    };
+
+    if let Some(message) = message {
+        let camera_transform = camera_query.single();
+        player.active = false;
+        spawn_textbox(
+            &mut commands,
+            &ascii,
+            &indices,
+            Vec2::new(0.0, 1.0 - 1.5 * TILE_SIZE)
+                + camera_transform.translation.truncate(),
+            &message,
+        );
+    }
+}
+
+/// The object or NPC an interact press goes to: the faced tile when it has
+/// one, else the closest within reach. Every interact handler asks this, so
+/// a press is only ever handled once. NPCs win over objects on their tile.
+pub fn interact_target<'a>(
+    grid: &'a TileGrid,
+    transform: &Transform,
+    facing: &FacingDirection,
+) -> Option<((i32, i32), &'a GridTile)> {
+    let usable =
+        |tile: &GridTile| tile.interact.is_some() || tile.npc.is_some();
+    let (x, y) = TileGrid::tile_coords(transform.translation);
+    let (dx, dy) = facing.tile_offset();
+    if let Some(tile) = grid.get(x + dx, y + dy).filter(|tile| usable(tile)) {
+        return Some(((x + dx, y + dy), tile));
+    }
+
+    let position = transform.translation.truncate();
+    grid.neighborhood(transform.translation)
+        .map(|(center, tile)| {
+            (center, tile, Vec2::distance(center.truncate(), position))
+        })
+        .filter(|(_, tile, distance)| {
+            usable(tile) && *distance <= TILE_SIZE * 1.5
+        })
+        .min_by(|(_, _, a), (_, _, b)| {
+            a.partial_cmp(b).unwrap_or(Ordering::Equal)
+        })
+        .map(|(center, tile, _)| (TileGrid::tile_coords(center), tile))
+}
+
+/// Draws `index` at `(x, y)`, changing the collider too if one is given.
+fn set_tile(
+    grid: &mut TileGrid,
+    (x, y): (i32, i32),
+    index: usize,
+    collider: Option<bool>,
+) {
+    let tile = match collider {
+        Some(_) => grid.collision_mut(x, y),
+        None => grid.get_mut(x, y),
+    };
+    if let Some(tile) = tile {
+        tile.index = index;
+        tile.collider = collider.unwrap_or(tile.collider);
+    }
+}
+
+/// Flips every tile toggled by `switch`, unless one would turn solid under
+/// the player at `player` or an NPC. Returns whether the tiles flipped.
+fn flip_switch(grid: &mut TileGrid, switch: &str, player: Vec3) -> bool {
+    let toggled: Vec<(i32, i32)> = grid
+        .iter()
+        .filter(|(_, tile)| {
+            matches!(&tile.toggle, Some(toggle) if toggle.switch == switch)
+        })
+        .map(|(position, _)| position)
+        .collect();
+
+    let occupied = toggled.iter().any(|&(x, y)| {
+        let tile = grid.get(x, y).expect("Toggled tile vanished!");
+        let translation = tile_translation(x as usize, y as usize).extend(0.0);
+        !tile.collider
+            && (tile.npc.is_some() || wall_collision_check(player, translation))
+    });
+    if occupied {
+        return false;
+    }
+
+    for (x, y) in toggled {
+        let tile = grid.collision_mut(x, y).expect("Toggled tile vanished!");
+        if let Some(toggle) = tile.toggle.as_mut() {
+            // The toggle keeps the glyph to show on the next flip
+            std::mem::swap(&mut tile.index, &mut toggle.index);
+            tile.collider = !tile.collider;
+        }
+    }
+    true
+}
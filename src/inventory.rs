@@ -0,0 +1,282 @@
+use std::{collections::HashMap, fs};
+
+use bevy::{prelude::*, reflect::FromReflect};
+use serde::Deserialize;
+
+use crate::equipment::{EquipSlot, StatBonus};
+
+/// Stacks a full inventory holds.
+const CAPACITY: usize = 20;
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum ItemKind {
+    /// Used up to heal `heal` health.
+    Consumable { heal: isize },
+    /// Carried for doors and quests, used up by the doors they open.
+    Key,
+    /// Money, counted rather than used.
+    Currency,
+    /// Worn in `slot`, adding `bonus` to the stats of the player.
+    Equipment {
+        slot: EquipSlot,
+        #[serde(default)]
+        bonus: StatBonus,
+    },
+}
+
+/// An item from `assets/items.ron`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct ItemDef {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub kind: ItemKind,
+    /// Most of the item a single stack holds, at least 1.
+    #[serde(default = "default_max_stack")]
+    pub max_stack: usize,
+}
+
+/// Every item by id, the ids chests, doors and shops refer to.
+pub struct ItemDefs(pub HashMap<String, ItemDef>);
+
+#[derive(Reflect, FromReflect, Clone, Debug, Default)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: usize,
+}
+
+/// Items the player carries, in stacks of at most the item's `max_stack`.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct Inventory {
+    stacks: Vec<ItemStack>,
+    capacity: usize,
+}
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(load_item_defs);
+    }
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory {
+            stacks: Vec::new(),
+            capacity: CAPACITY,
+        }
+    }
+}
+
+impl ItemDefs {
+    /// Reads `assets/items.ron`, rejecting items no stack could hold.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let defs: HashMap<String, ItemDef> = ron::from_str(text)?;
+        let mut empty: Vec<&str> = defs
+            .iter()
+            .filter(|(_, def)| def.max_stack < 1)
+            .map(|(item, _)| item.as_str())
+            .collect();
+        empty.sort_unstable();
+        if !empty.is_empty() {
+            anyhow::bail!("max_stack below 1 for {}", empty.join(", "));
+        }
+        Ok(ItemDefs(defs))
+    }
+
+    pub fn get(&self, item: &str) -> Option<&ItemDef> {
+        self.0.get(item)
+    }
+
+    /// Display name of `item`, its id when it has no definition.
+    pub fn name<'a>(&'a self, item: &'a str) -> &'a str {
+        self.get(item).map_or(item, |def| def.name.as_str())
+    }
+}
+
+impl Inventory {
+    /// How many more of `item` fit, topping up its stacks and filling the
+    /// free ones. Unknown items never fit.
+    pub fn room_for(&self, defs: &ItemDefs, item: &str) -> usize {
+        let max_stack = match defs.get(item) {
+            Some(def) => def.max_stack,
+            None => return 0,
+        };
+        let topped_up: usize = self
+            .stacks
+            .iter()
+            .filter(|stack| stack.item == item)
+            .map(|stack| max_stack.saturating_sub(stack.count))
+            .sum();
+        let free = self.capacity.saturating_sub(self.stacks.len());
+        topped_up + free * max_stack
+    }
+
+    /// Adds as much of `count` as fits, returning what didn't.
+    pub fn add(&mut self, defs: &ItemDefs, item: &str, count: usize) -> usize {
+        let max_stack = match defs.get(item) {
+            Some(def) => def.max_stack,
+            None => {
+                warn!("Unknown item '{}'", item);
+                return count;
+            }
+        };
+
+        let mut left = count;
+        for stack in self.stacks.iter_mut().filter(|stack| stack.item == item) {
+            let added = left.min(max_stack.saturating_sub(stack.count));
+            stack.count += added;
+            left -= added;
+        }
+        while left > 0 && self.stacks.len() < self.capacity {
+            let added = left.min(max_stack);
+            self.stacks.push(ItemStack {
+                item: item.to_string(),
+                count: added,
+            });
+            left -= added;
+        }
+        left
+    }
+
+    /// Takes `count` of `item`, only if there are at least that many. The
+    /// last stacks are emptied first.
+    pub fn remove(&mut self, item: &str, count: usize) -> bool {
+        if self.count(item) < count {
+            return false;
+        }
+
+        let mut left = count;
+        for stack in self.stacks.iter_mut().rev() {
+            if stack.item == item {
+                let taken = left.min(stack.count);
+                stack.count -= taken;
+                left -= taken;
+            }
+        }
+        self.stacks.retain(|stack| stack.count > 0);
+        true
+    }
+
+    pub fn count(&self, item: &str) -> usize {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.count(item) > 0
+    }
+
+    pub fn stacks(&self) -> &[ItemStack] {
+        &self.stacks
+    }
+
+    /// Ids and total counts of the carried items matching `filter`, e.g. all
+    /// consumables for a combat menu.
+    pub fn items_where(
+        &self,
+        defs: &ItemDefs,
+        filter: impl Fn(&ItemDef) -> bool,
+    ) -> Vec<(String, usize)> {
+        let mut items: Vec<(String, usize)> = Vec::new();
+        for stack in &self.stacks {
+            if !defs.get(&stack.item).map_or(false, &filter) {
+                continue;
+            }
+            match items.iter_mut().find(|(item, _)| *item == stack.item) {
+                Some((_, count)) => *count += stack.count,
+                None => items.push((stack.item.clone(), stack.count)),
+            }
+        }
+        items
+    }
+}
+
+fn default_max_stack() -> usize {
+    99
+}
+
+fn load_item_defs(mut commands: Commands) {
+    let text = fs::read_to_string("assets/items.ron")
+        .expect("No item definitions found!");
+    let defs = ItemDefs::parse(&text)
+        .unwrap_or_else(|err| panic!("Invalid item definitions: {}!", err));
+    commands.insert_resource(defs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defs() -> ItemDefs {
+        ItemDefs::parse(
+            r#"{
+                "potion": (
+                    name: "Potion",
+                    kind: Consumable(heal: 5),
+                    max_stack: 3,
+                ),
+                "key": (name: "Key", kind: Key, max_stack: 1),
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn inventory(capacity: usize) -> Inventory {
+        Inventory {
+            stacks: Vec::new(),
+            capacity,
+        }
+    }
+
+    fn counts(inventory: &Inventory) -> Vec<usize> {
+        inventory.stacks().iter().map(|stack| stack.count).collect()
+    }
+
+    #[test]
+    fn rejects_empty_stacks() {
+        let text = r#"{"air": (name: "Air", kind: Key, max_stack: 0)}"#;
+        assert!(ItemDefs::parse(text).is_err());
+    }
+
+    #[test]
+    fn overflows_into_new_stacks() {
+        let defs = defs();
+        let mut inventory = inventory(5);
+        assert_eq!(inventory.add(&defs, "potion", 2), 0);
+        assert_eq!(inventory.add(&defs, "potion", 5), 0);
+        assert_eq!(counts(&inventory), [3, 3, 1]);
+        assert_eq!(inventory.count("potion"), 7);
+    }
+
+    #[test]
+    fn full_inventory_returns_the_rest() {
+        let defs = defs();
+        let mut inventory = inventory(2);
+        assert_eq!(inventory.add(&defs, "key", 1), 0);
+        assert_eq!(inventory.room_for(&defs, "potion"), 3);
+        assert_eq!(inventory.add(&defs, "potion", 5), 2);
+        assert_eq!(inventory.room_for(&defs, "key"), 0);
+        assert_eq!(inventory.add(&defs, "key", 1), 1);
+        assert_eq!(inventory.add(&defs, "unknown", 4), 4);
+        assert_eq!(counts(&inventory), [1, 3]);
+    }
+
+    #[test]
+    fn removes_from_the_last_stacks() {
+        let defs = defs();
+        let mut inventory = inventory(5);
+        inventory.add(&defs, "potion", 7);
+        assert!(inventory.remove("potion", 2));
+        assert_eq!(counts(&inventory), [3, 2]);
+        assert!(inventory.remove("potion", 3));
+        assert_eq!(counts(&inventory), [2]);
+        assert!(!inventory.remove("potion", 3));
+        assert_eq!(inventory.count("potion"), 2);
+    }
+}
@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+
+pub mod actions;
+pub mod ascii;
+pub mod audio;
+pub mod chunk;
+pub mod clock;
+pub mod combat;
+pub mod controls;
+pub mod debug;
+pub mod encounter;
+pub mod equipment;
+pub mod fadeout;
+pub mod fov;
+pub mod generator;
+pub mod graphics;
+pub mod interact;
+pub mod inventory;
+pub mod map;
+pub mod minimap;
+pub mod npc;
+pub mod pathfinding;
+pub mod player;
+pub mod start_menu;
+pub mod tiled;
+pub mod tilemap;
+pub mod validation;
+pub mod weather;
+
+pub const CLEAR: Color = Color::rgb(0.1, 0.1, 0.1);
+pub const RESOLUTION: f32 = 16.0 / 9.0;
+pub const TILE_SIZE: f32 = 0.1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
+pub enum GameState {
+    StartMenu,
+    Overworld,
+    Combat,
+}
@@ -1,37 +1,33 @@
-use bevy::{app::AppExit, prelude::*, render::camera::ScalingMode};
+use bevy::{
+    app::AppExit, asset::AssetServerSettings, prelude::*,
+    render::camera::ScalingMode,
+};
 
-mod ascii;
-mod audio;
-mod combat;
-mod debug;
-mod fadeout;
-mod graphics;
-mod npc;
-mod player;
-mod start_menu;
-mod tilemap;
-
-use ascii::AsciiPlugin;
-use audio::GameAudioPlugin;
-use combat::CombatPlugin;
-use debug::DebugPlugin;
-use fadeout::FadeoutPlugin;
-use graphics::GraphicsPlugin;
-use npc::NpcPlugin;
-use player::PlayerPlugin;
-use start_menu::MainMenuPlugin;
-use tilemap::TileMapPlugin;
-
-pub const CLEAR: Color = Color::rgb(0.1, 0.1, 0.1);
-pub const RESOLUTION: f32 = 16.0 / 9.0;
-pub const TILE_SIZE: f32 = 0.1;
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
-pub enum GameState {
-    StartMenu,
-    Overworld,
-    Combat,
-}
+use bevy_tutorial::{
+    actions::{Action, ActionPlugin, ActionState},
+    ascii::AsciiPlugin,
+    audio::GameAudioPlugin,
+    chunk::ChunkPlugin,
+    clock::ClockPlugin,
+    combat::CombatPlugin,
+    controls::ControlsPlugin,
+    debug::DebugPlugin,
+    encounter::EncounterPlugin,
+    equipment::EquipmentPlugin,
+    fadeout::FadeoutPlugin,
+    fov::FovPlugin,
+    graphics::GraphicsPlugin,
+    interact::InteractPlugin,
+    inventory::InventoryPlugin,
+    minimap::MinimapPlugin,
+    npc::NpcPlugin,
+    pathfinding::PathfindingPlugin,
+    player::PlayerPlugin,
+    start_menu::MainMenuPlugin,
+    tilemap::TileMapPlugin,
+    weather::WeatherPlugin,
+    GameState, CLEAR, RESOLUTION,
+};
 
 fn main() {
     let height = 900.0;
@@ -47,18 +43,34 @@ fn main() {
             resizable: false,
             ..default()
         })
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: true,
+            ..default()
+        })
         .add_plugins(DefaultPlugins)
         .add_startup_system(spawn_camera)
+        .add_plugin(ActionPlugin)
         .add_plugin(AsciiPlugin)
+        .add_plugin(ChunkPlugin)
+        .add_plugin(ClockPlugin)
+        .add_plugin(ControlsPlugin)
         .add_plugin(CombatPlugin)
         .add_plugin(DebugPlugin)
+        .add_plugin(EncounterPlugin)
+        .add_plugin(EquipmentPlugin)
         .add_plugin(FadeoutPlugin)
+        .add_plugin(FovPlugin)
+        .add_plugin(InteractPlugin)
+        .add_plugin(InventoryPlugin)
         .add_plugin(GameAudioPlugin)
+        .add_plugin(MinimapPlugin)
+        .add_plugin(PathfindingPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(TileMapPlugin)
         .add_plugin(GraphicsPlugin)
         .add_plugin(MainMenuPlugin)
         .add_plugin(NpcPlugin)
+        .add_plugin(WeatherPlugin)
         .add_system(check_for_exit)
         .run();
 }
@@ -76,11 +88,8 @@ fn spawn_camera(mut commands: Commands) {
     commands.spawn_bundle(camera);
 }
 
-fn check_for_exit(
-    keyboard: Res<Input<KeyCode>>,
-    mut events: EventWriter<AppExit>,
-) {
-    if keyboard.pressed(KeyCode::LControl) && keyboard.pressed(KeyCode::Q) {
+fn check_for_exit(actions: Res<ActionState>, mut events: EventWriter<AppExit>) {
+    if actions.pressed(Action::Quit) {
         events.send(AppExit);
     }
 }
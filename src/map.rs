@@ -0,0 +1,396 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use ron::{extensions::Extensions, ser::PrettyConfig};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    clock::TimeOfDay,
+    generator::GeneratorConfig,
+    interact::{Interactable, Toggle},
+    npc::Npc,
+    tilemap::Warp,
+    validation::{check_map, Diagnostic, MapError, Position, Severity},
+    weather::Weather,
+};
+
+/// Line that ends the RON header and starts the ASCII layout of a map file.
+pub const HEADER_SEPARATOR: &str = "---";
+
+/// Entity kinds a legend entry can place on its tiles.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum MapEntity {
+    Npc(Npc),
+}
+
+/// What a single glyph in the layout means.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct TileDef {
+    /// Atlas index to draw, defaults to the glyph itself.
+    #[serde(default)]
+    pub index: Option<usize>,
+    #[serde(default = "default_color")]
+    pub color: (f32, f32, f32),
+    #[serde(default)]
+    pub collider: bool,
+    /// Encounter zone id, tiles without one never start fights.
+    #[serde(default)]
+    pub encounter: Option<String>,
+    #[serde(default)]
+    pub spawn: Option<MapEntity>,
+    /// Stepping on the tile moves the player to another map.
+    #[serde(default)]
+    pub warp: Option<Warp>,
+    /// Named arrival point for warps targeting this map.
+    #[serde(default)]
+    pub spawn_point: Option<String>,
+    /// Where the player appears when a new game starts on this map.
+    #[serde(default)]
+    pub player_start: bool,
+    #[serde(default)]
+    pub animation: Option<TileAnimation>,
+    #[serde(default)]
+    pub terrain: Terrain,
+    /// Chest, sign, door or switch the player can use.
+    #[serde(default)]
+    pub interact: Option<Interactable>,
+    /// Switch that flips the collider of this tile.
+    #[serde(default)]
+    pub toggle: Option<Toggle>,
+    /// Light radius in tiles, tiles around a light keep their color at
+    /// night.
+    #[serde(default)]
+    pub light: Option<f32>,
+    /// The NPC on the tile is only around at this time of day.
+    #[serde(default)]
+    pub available: Option<TimeOfDay>,
+    /// Weather while the player is on the tile, overriding the map weather.
+    #[serde(default)]
+    pub weather: Option<Weather>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// How the player moves around a map.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Any distance in any direction, sliding along colliders.
+    Free,
+    /// One tile at a time, in the four directions.
+    Grid,
+}
+
+/// How a walkable tile affects the player walking on it.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Terrain {
+    /// Multiplier on the player speed.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Health lost every second on the tile, never below 1.
+    #[serde(default)]
+    pub damage: isize,
+    /// Ice, the player keeps sliding until something stops them.
+    #[serde(default)]
+    pub slippery: bool,
+    /// Ledges can only be entered moving in this direction.
+    #[serde(default)]
+    pub one_way: Option<Direction>,
+}
+
+/// Glyphs and colors an animated tile cycles through, either may be empty.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct TileAnimation {
+    /// Seconds every frame is shown.
+    pub frame_time: f32,
+    /// Atlas indices.
+    #[serde(default)]
+    pub frames: Vec<usize>,
+    #[serde(default)]
+    pub colors: Vec<(f32, f32, f32)>,
+}
+
+/// Every map the game can load, keyed by the id warps refer to.
+#[derive(Deserialize, Debug)]
+pub struct MapRegistry {
+    pub start: String,
+    /// Asset paths of the maps, relative to the assets folder.
+    pub maps: HashMap<String, String>,
+    /// Maps generated every time a warp leads to them.
+    #[serde(default)]
+    pub generated: HashMap<String, GeneratorConfig>,
+    #[serde(skip)]
+    pub handles: HashMap<String, Handle<MapData>>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MapHeader {
+    pub name: String,
+    /// Written sorted by glyph, so saving a map always gives the same text.
+    #[serde(serialize_with = "sorted")]
+    pub legend: HashMap<char, TileDef>,
+    /// Sight radius in tiles, maps without one are fully visible.
+    #[serde(default)]
+    pub fog_of_war: Option<i32>,
+    /// Indoor maps don't get darker at night.
+    #[serde(default)]
+    pub indoors: bool,
+    #[serde(default)]
+    pub weather: Option<Weather>,
+    /// Overrides the movement mode of the `MovementSettings` resource.
+    #[serde(default)]
+    pub movement: Option<MovementMode>,
+}
+
+#[derive(TypeUuid)]
+#[uuid = "6b1f3a52-8d2e-4c7b-9a0f-3e5d7c1b2a94"]
+pub struct MapData {
+    pub header: MapHeader,
+    pub rows: Vec<Vec<char>>,
+    /// 1-based file line of the first layout row, for diagnostics. `None`
+    /// for maps not read from a map file, positioned by tile instead.
+    pub layout_line: Option<usize>,
+}
+
+pub fn rgb((r, g, b): (f32, f32, f32)) -> Color {
+    Color::rgb(r, g, b)
+}
+
+fn default_color() -> (f32, f32, f32) {
+    (0.9, 0.9, 0.9)
+}
+
+impl Default for TileDef {
+    fn default() -> Self {
+        TileDef {
+            index: None,
+            color: default_color(),
+            collider: false,
+            encounter: None,
+            spawn: None,
+            warp: None,
+            spawn_point: None,
+            player_start: false,
+            animation: None,
+            terrain: Terrain::default(),
+            interact: None,
+            toggle: None,
+            light: None,
+            available: None,
+            weather: None,
+        }
+    }
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain {
+            speed: default_speed(),
+            damage: 0,
+            slippery: false,
+            one_way: None,
+        }
+    }
+}
+
+impl TileDef {
+    pub fn atlas_index(&self, glyph: char) -> usize {
+        self.index.unwrap_or(glyph as usize)
+    }
+
+    pub fn color(&self) -> Color {
+        rgb(self.color)
+    }
+}
+
+impl MapData {
+    /// Splits a map file into its RON legend header and the glyph layout.
+    pub fn parse(text: &str) -> Result<Self, Diagnostic> {
+        let mut header = String::new();
+        let mut layout_line = None;
+        let mut lines = text.lines().enumerate();
+        for (number, line) in lines.by_ref() {
+            if line.trim_end() == HEADER_SEPARATOR {
+                layout_line = Some(number + 2);
+                break;
+            }
+            header.push_str(line);
+            header.push('\n');
+        }
+
+        let layout_line = layout_line.ok_or_else(|| Diagnostic {
+            severity: Severity::Error,
+            position: Position::Text {
+                line: text.lines().count().max(1),
+                column: 1,
+            },
+            message: format!(
+                "missing '{}' line after the header",
+                HEADER_SEPARATOR
+            ),
+        })?;
+
+        let header: MapHeader =
+            ron::from_str(&header).map_err(|err| Diagnostic {
+                severity: Severity::Error,
+                position: Position::Text {
+                    line: err.position.line,
+                    column: err.position.col,
+                },
+                message: err.code.to_string(),
+            })?;
+
+        let mut rows: Vec<Vec<char>> =
+            lines.map(|(_, line)| line.chars().collect()).collect();
+        while rows
+            .last()
+            .map_or(false, |row| row.iter().all(|glyph| glyph.is_whitespace()))
+        {
+            rows.pop();
+        }
+
+        Ok(MapData {
+            header,
+            rows,
+            layout_line: Some(layout_line),
+        })
+    }
+
+    /// Formats the map the same way map files are written.
+    pub fn to_map_file(&self) -> Result<String, ron::Error> {
+        let config = PrettyConfig::new().extensions(Extensions::IMPLICIT_SOME);
+        let mut text = ron::ser::to_string_pretty(&self.header, config)?;
+        text.push('\n');
+        text.push_str(HEADER_SEPARATOR);
+        text.push('\n');
+        for row in self.rows.iter() {
+            text.extend(row.iter());
+            text.push('\n');
+        }
+        Ok(text)
+    }
+
+    pub fn tile_def(&self, glyph: char) -> Option<&TileDef> {
+        self.header.legend.get(&glyph)
+    }
+
+    /// Tile coordinates of the first tile whose legend entry matches.
+    pub fn find_tile(
+        &self,
+        predicate: impl Fn(&TileDef) -> bool,
+    ) -> Option<(usize, usize)> {
+        self.rows.iter().enumerate().find_map(|(y, row)| {
+            row.iter()
+                .position(|glyph| {
+                    self.tile_def(*glyph).map_or(false, &predicate)
+                })
+                .map(|x| (x, y))
+        })
+    }
+
+    pub fn spawn_point(&self, name: &str) -> Option<(usize, usize)> {
+        self.find_tile(|def| def.spawn_point.as_deref() == Some(name))
+    }
+
+    pub fn player_start(&self) -> Option<(usize, usize)> {
+        self.find_tile(|def| def.player_start)
+    }
+
+    /// Where the player arrives without a spawn point to go to, the player
+    /// start or else the first spawn point. Valid maps always have one.
+    pub fn arrival(&self) -> Option<(usize, usize)> {
+        self.player_start()
+            .or_else(|| self.find_tile(|def| def.spawn_point.is_some()))
+    }
+}
+
+fn sorted<S: Serializer>(
+    legend: &HashMap<char, TileDef>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    legend
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+pub(crate) fn log_diagnostics(path: &Path, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics.iter() {
+        match diagnostic.severity {
+            Severity::Warning => warn!("{}:{}", path.display(), diagnostic),
+            Severity::Error => error!("{}:{}", path.display(), diagnostic),
+        }
+    }
+}
+
+/// Loads `*.map.txt` files as [`MapData`] assets.
+#[derive(Default)]
+pub struct MapLoader;
+
+impl AssetLoader for MapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let (map, diagnostics) = check_map(std::str::from_utf8(bytes)?);
+            log_diagnostics(load_context.path(), &diagnostics);
+
+            let map = map.ok_or(MapError { diagnostics })?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.txt"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parsing what was written gives the same map, and writes the same text.
+    fn assert_round_trip(text: &str) {
+        let map = MapData::parse(text).unwrap();
+        let written = map.to_map_file().unwrap();
+        let parsed = MapData::parse(&written).unwrap();
+        assert_eq!(parsed.header.legend, map.header.legend);
+        assert_eq!(parsed.rows, map.rows);
+        assert_eq!(parsed.to_map_file().unwrap(), written);
+    }
+
+    #[test]
+    fn map_files_round_trip() {
+        assert_round_trip(include_str!("../assets/maps/overworld.map.txt"));
+        assert_round_trip(include_str!("../assets/maps/town.map.txt"));
+        assert_round_trip(include_str!("../assets/maps/dungeon.map.txt"));
+    }
+
+    #[test]
+    fn layout_starts_after_the_separator() {
+        let map =
+            MapData::parse("(name: \"A\", legend: {})\n---\n..\n\n").unwrap();
+        assert_eq!(map.layout_line, Some(3));
+        assert_eq!(map.rows, [['.', '.']]);
+    }
+}
@@ -0,0 +1,180 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    actions::{Action, ActionState},
+    graphics::shade,
+    player::Player,
+    tilemap::{GridTile, TileGrid},
+    GameState,
+};
+
+/// Longest side of the minimap on screen, in pixels.
+const MINIMAP_SIZE: f32 = 200.0;
+/// Screen pixels per tile are capped so small maps don't fill the corner.
+const MAX_TILE_PIXELS: f32 = 6.0;
+/// Walkable tiles are drawn darker, so walls stand out.
+const FLOOR_BRIGHTNESS: f32 = 0.4;
+const PLAYER_COLOR: Color = Color::rgb(1.0, 1.0, 0.2);
+const NPC_COLOR: Color = Color::rgb(0.2, 1.0, 0.3);
+const WARP_COLOR: Color = Color::rgb(1.0, 0.2, 1.0);
+
+/// Overlay in the top right corner drawing the current map at one pixel per
+/// tile.
+#[derive(Component)]
+pub struct Minimap;
+
+/// Whether the player wants the minimap, it is hidden outside the overworld
+/// either way.
+#[derive(Default)]
+pub struct MinimapSettings {
+    pub shown: bool,
+}
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MinimapSettings>()
+            .add_startup_system(spawn_minimap)
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(toggle_minimap)
+                    .with_system(draw_minimap.after(toggle_minimap)),
+            )
+            .add_system_set(
+                SystemSet::on_resume(GameState::Overworld)
+                    .with_system(show_minimap),
+            )
+            .add_system_set(
+                SystemSet::on_pause(GameState::Overworld)
+                    .with_system(hide_minimap),
+            );
+    }
+}
+
+fn spawn_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = images.add(minimap_image(1, 1));
+
+    commands
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(16.0),
+                    right: Val::Px(16.0),
+                    ..default()
+                },
+                ..default()
+            },
+            image: image.into(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(Name::new("Minimap"))
+        .insert(Minimap);
+}
+
+fn minimap_image(width: usize, height: usize) -> Image {
+    Image::new_fill(
+        Extent3d {
+            width: width.max(1) as u32,
+            height: height.max(1) as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn toggle_minimap(
+    mut settings: ResMut<MinimapSettings>,
+    actions: Res<ActionState>,
+) {
+    if actions.just_pressed(Action::Minimap) {
+        settings.shown = !settings.shown;
+    }
+}
+
+fn show_minimap(
+    settings: Res<MinimapSettings>,
+    mut minimap_query: Query<&mut Visibility, With<Minimap>>,
+) {
+    for mut visibility in minimap_query.iter_mut() {
+        visibility.is_visible = settings.shown;
+    }
+}
+
+fn hide_minimap(mut minimap_query: Query<&mut Visibility, With<Minimap>>) {
+    for mut visibility in minimap_query.iter_mut() {
+        visibility.is_visible = false;
+    }
+}
+
+/// Redraws the minimap when it is shown, the map changes or the player
+/// moves to another tile.
+fn draw_minimap(
+    mut minimap_query: Query<
+        (&mut Visibility, &mut Style, &UiImage),
+        With<Minimap>,
+    >,
+    player_query: Query<&Transform, With<Player>>,
+    settings: Res<MinimapSettings>,
+    grid: Res<TileGrid>,
+    mut images: ResMut<Assets<Image>>,
+    mut player_tile: Local<Option<(i32, i32)>>,
+) {
+    let (mut visibility, mut style, image) = minimap_query.single_mut();
+    let tile = TileGrid::tile_coords(player_query.single().translation);
+    if settings.is_changed() {
+        visibility.is_visible = settings.shown;
+    }
+    let moved = *player_tile != Some(tile);
+    if !settings.shown || !(moved || settings.is_changed() || grid.is_changed())
+    {
+        return;
+    }
+    *player_tile = Some(tile);
+
+    let image = match images.get_mut(&image.0) {
+        Some(image) => image,
+        None => return,
+    };
+    *image = minimap_image(grid.width, grid.height);
+    for ((x, y), grid_tile) in grid.iter() {
+        let color = if (x, y) == tile {
+            PLAYER_COLOR
+        } else {
+            tile_color(grid_tile)
+        };
+        let pixel = (y as usize * grid.width + x as usize) * 4;
+        for (channel, value) in color.as_rgba_f32().iter().enumerate() {
+            image.data[pixel + channel] = (value * 255.0) as u8;
+        }
+    }
+
+    let scale = (MINIMAP_SIZE / grid.width.max(grid.height).max(1) as f32)
+        .min(MAX_TILE_PIXELS);
+    style.size = Size::new(
+        Val::Px(grid.width as f32 * scale),
+        Val::Px(grid.height as f32 * scale),
+    );
+}
+
+/// Tiles the fog of war still hides stay blank.
+fn tile_color(tile: &GridTile) -> Color {
+    if tile.brightness <= 0.0 {
+        Color::NONE
+    } else if tile.npc.is_some() {
+        NPC_COLOR
+    } else if tile.warp.is_some() {
+        WARP_COLOR
+    } else if tile.collider {
+        tile.color
+    } else {
+        shade(tile.color, FLOOR_BRIGHTNESS)
+    }
+}
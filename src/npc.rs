@@ -1,16 +1,23 @@
 use bevy::{prelude::*, render::camera::Camera2d};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    actions::{Action, ActionState},
     ascii::{
         spawn_ascii_sprite, spawn_ascii_text, spawn_nine_slice, AsciiSheet,
         NineSliceIndicies,
     },
+    clock::GameClock,
     combat::CombatStats,
+    equipment::{derive_stats, EffectiveStats},
+    graphics::PlayerGraphics,
+    interact::interact_target,
     player::Player,
+    tilemap::TileGrid,
     GameState, CLEAR, TILE_SIZE,
 };
 
-#[derive(Component)]
+#[derive(Component, Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
 pub enum Npc {
     Healer,
 }
@@ -24,20 +31,20 @@ impl Plugin for NpcPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(
             SystemSet::on_update(GameState::Overworld)
-                .with_system(npc_speech)
+                .with_system(npc_speech.after(derive_stats))
                 .with_system(clear_speech.after(npc_speech)),
         );
     }
 }
 
-fn clear_speech(
+pub fn clear_speech(
     mut commands: Commands,
     mut player_query: Query<&mut Player>,
     speech_query: Query<Entity, With<NpcText>>,
-    keyboard: Res<Input<KeyCode>>,
+    actions: Res<ActionState>,
 ) {
     let mut player = player_query.single_mut();
-    if keyboard.any_just_pressed([KeyCode::Space, KeyCode::E]) {
+    if actions.any_just_pressed([Action::Confirm, Action::Interact]) {
         for ent in speech_query.iter() {
             player.active = true;
             commands.entity(ent).despawn_recursive();
@@ -45,7 +52,7 @@ fn clear_speech(
     }
 }
 
-fn spawn_textbox(
+pub fn spawn_textbox(
     commands: &mut Commands,
     ascii: &AsciiSheet,
     indices: &NineSliceIndicies,
@@ -84,36 +91,48 @@ fn spawn_textbox(
 
 fn npc_speech(
     mut commands: Commands,
-    mut player_query: Query<(&mut Player, &mut CombatStats, &Transform)>,
+    mut player_query: Query<(
+        &mut Player,
+        &mut CombatStats,
+        &EffectiveStats,
+        &Transform,
+        &PlayerGraphics,
+    )>,
     camera_query: Query<&Transform, With<Camera2d>>,
-    npc_query: Query<(&Npc, &Transform)>,
-    keyboard: Res<Input<KeyCode>>,
+    grid: Res<TileGrid>,
+    clock: Res<GameClock>,
+    actions: Res<ActionState>,
     ascii: Res<AsciiSheet>,
     indices: Res<NineSliceIndicies>,
 ) {
-    let (mut player, mut stats, transform) = player_query.single_mut();
+    let (mut player, mut stats, effective, transform, graphics) =
+        player_query.single_mut();
     let camera_transform = camera_query.single();
-    if !player.active {
+    if !player.active || !actions.just_pressed(Action::Interact) {
         return;
     }
-    if keyboard.just_pressed(KeyCode::E) {
-        for (_npc, npc_transform) in npc_query.iter() {
-            if Vec2::distance(
-                npc_transform.translation.truncate(),
-                transform.translation.truncate(),
-            ) <= TILE_SIZE * 1.5
-            {
-                player.active = false;
-                stats.health = stats.max_health;
-                spawn_textbox(
-                    &mut commands,
-                    &ascii,
-                    &indices,
-                    Vec2::new(0.0, 1.0 - 1.5 * TILE_SIZE)
-                        + camera_transform.translation.truncate(),
-                    "You seem weak, let me heal you!",
-                );
-            }
-        }
-    }
+    let tile = match interact_target(&grid, transform, &graphics.facing) {
+        Some((_, tile)) if tile.npc.is_some() => tile,
+        _ => return,
+    };
+
+    // NPCs sleep outside of the time they are around
+    let awake = tile
+        .available
+        .map_or(true, |time| time == clock.time_of_day());
+    let text = if awake {
+        stats.health = effective.max_health;
+        "You seem weak, let me heal you!"
+    } else {
+        "Zzz..."
+    };
+    player.active = false;
+    spawn_textbox(
+        &mut commands,
+        &ascii,
+        &indices,
+        Vec2::new(0.0, 1.0 - 1.5 * TILE_SIZE)
+            + camera_transform.translation.truncate(),
+        text,
+    );
 }
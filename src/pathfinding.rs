@@ -0,0 +1,312 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use bevy::prelude::*;
+
+use crate::{map::Direction, tilemap::TileGrid};
+
+/// Paths remembered before the cache starts over.
+const MAX_CACHED_PATHS: usize = 256;
+/// Extra cost of stepping on a tile for every point of damage it deals.
+const DAMAGE_COST: f32 = 4.0;
+
+type PathKey = ((i32, i32), (i32, i32), PathCosts);
+
+/// How steps between tiles are weighed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PathCosts {
+    /// Every step costs the same, for the shortest path.
+    Uniform,
+    /// Slow and damaging terrain costs more, for the path a walker prefers.
+    Terrain,
+}
+
+/// A* over the collision grid of the current map. Paths are cached until
+/// colliders or terrain change, e.g. on warps or when a switch opens a gate.
+#[derive(Default)]
+pub struct Pathfinder {
+    cache: HashMap<PathKey, Option<Vec<(i32, i32)>>>,
+    /// [`TileGrid::revision`] the cached paths were found on.
+    revision: u64,
+}
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Pathfinder>();
+    }
+}
+
+#[derive(PartialEq)]
+struct Open {
+    estimate: f32,
+    tile: (i32, i32),
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    // Reversed, the heap pops the lowest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Pathfinder {
+    /// Tiles to walk through from `from` to `to`, ending with `to` and empty
+    /// if they are the same tile. `None` when `to` can't be reached.
+    pub fn find_path(
+        &mut self,
+        grid: &TileGrid,
+        from: (i32, i32),
+        to: (i32, i32),
+        costs: PathCosts,
+    ) -> Option<Vec<(i32, i32)>> {
+        if self.revision != grid.revision()
+            || self.cache.len() >= MAX_CACHED_PATHS
+        {
+            self.cache.clear();
+            self.revision = grid.revision();
+        }
+        self.cache
+            .entry((from, to, costs))
+            .or_insert_with(|| a_star(grid, from, to, costs))
+            .clone()
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn cached_paths(&self) -> usize {
+        self.cache.len()
+    }
+}
+
+/// Cost of entering `tile`, `None` if it can't be entered moving `heading`.
+fn step_cost(
+    grid: &TileGrid,
+    tile: (i32, i32),
+    heading: Direction,
+    costs: PathCosts,
+) -> Option<f32> {
+    let tile = grid.get(tile.0, tile.1)?;
+    let wrong_way = tile
+        .terrain
+        .one_way
+        .map_or(false, |one_way| one_way != heading);
+    if tile.collider || wrong_way || tile.terrain.speed <= 0.0 {
+        return None;
+    }
+
+    match costs {
+        PathCosts::Uniform => Some(1.0),
+        PathCosts::Terrain => Some(
+            1.0 / tile.terrain.speed
+                + tile.terrain.damage.max(0) as f32 * DAMAGE_COST,
+        ),
+    }
+}
+
+fn a_star(
+    grid: &TileGrid,
+    from: (i32, i32),
+    to: (i32, i32),
+    costs: PathCosts,
+) -> Option<Vec<(i32, i32)>> {
+    grid.get(to.0, to.1).filter(|tile| !tile.collider)?;
+    if from == to {
+        return Some(Vec::new());
+    }
+    // Fast terrain makes steps cheaper than 1, the estimate must never
+    // be above the real cost
+    let cheapest_step = match costs {
+        PathCosts::Uniform => 1.0,
+        PathCosts::Terrain => grid
+            .iter()
+            .map(|(_, tile)| 1.0 / tile.terrain.speed.max(1.0))
+            .fold(1.0, f32::min),
+    };
+    let estimate = |(x, y): (i32, i32)| {
+        ((x - to.0).abs() + (y - to.1).abs()) as f32 * cheapest_step
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut cost = HashMap::new();
+    open.push(Open {
+        estimate: estimate(from),
+        tile: from,
+    });
+    cost.insert(from, 0.0);
+
+    while let Some(Open { tile, .. }) = open.pop() {
+        if tile == to {
+            let mut path = vec![to];
+            while let Some(&previous) = came_from.get(path.last()?) {
+                if previous == from {
+                    break;
+                }
+                path.push(previous);
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let (x, y) = tile;
+        for (next, heading) in [
+            ((x, y - 1), Direction::Up),
+            ((x, y + 1), Direction::Down),
+            ((x - 1, y), Direction::Left),
+            ((x + 1, y), Direction::Right),
+        ] {
+            let step = match step_cost(grid, next, heading, costs) {
+                Some(step) => step,
+                None => continue,
+            };
+            let next_cost = cost[&tile] + step;
+            if cost.get(&next).map_or(true, |&known| next_cost < known) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, tile);
+                open.push(Open {
+                    estimate: next_cost + estimate(next),
+                    tile: next,
+                });
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{map::Terrain, tilemap::GridTile};
+
+    /// Grid from rows of `#` walls and `.` floor.
+    fn grid(rows: &[&str]) -> TileGrid {
+        let mut grid = TileGrid::new(rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                grid.set(
+                    x,
+                    y,
+                    GridTile {
+                        index: glyph as usize,
+                        color: Color::WHITE,
+                        animation: None,
+                        brightness: 1.0,
+                        light: 1.0,
+                        collider: glyph == '#',
+                        encounter: None,
+                        warp: None,
+                        terrain: Terrain::default(),
+                        weather: None,
+                        npc: None,
+                        available: None,
+                        interact: None,
+                        toggle: None,
+                    },
+                );
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let grid = grid(&[
+            ".....", //
+            ".###.", "...#.",
+        ]);
+        let path = a_star(&grid, (0, 2), (4, 2), PathCosts::Uniform)
+            .expect("No path around the wall!");
+        assert_eq!(path.len(), 8);
+        assert_eq!(path.last(), Some(&(4, 2)));
+        assert!(path.iter().all(|&(x, y)| !grid.get(x, y).unwrap().collider));
+    }
+
+    #[test]
+    fn never_steps_diagonally() {
+        let grid = grid(&["...", "...", "..."]);
+        let path = a_star(&grid, (0, 0), (2, 2), PathCosts::Uniform)
+            .expect("No path on an open grid!");
+        assert_eq!(path.len(), 4);
+        let mut previous = (0, 0);
+        for &(x, y) in &path {
+            assert_eq!((x - previous.0).abs() + (y - previous.1).abs(), 1);
+            previous = (x, y);
+        }
+    }
+
+    #[test]
+    fn no_path_to_walled_off_or_solid_goals() {
+        let grid = grid(&[
+            "..#..", //
+            "..#..", "..###",
+        ]);
+        assert_eq!(a_star(&grid, (0, 0), (4, 0), PathCosts::Uniform), None);
+        assert_eq!(a_star(&grid, (0, 0), (2, 0), PathCosts::Uniform), None);
+        assert_eq!(a_star(&grid, (0, 0), (9, 9), PathCosts::Uniform), None);
+        assert_eq!(
+            a_star(&grid, (1, 1), (1, 1), PathCosts::Uniform),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn cache_hits_until_walls_change() {
+        let mut grid = grid(&[
+            "...", //
+            ".#.", "...",
+        ]);
+        let mut pathfinder = Pathfinder::default();
+        let around = pathfinder
+            .find_path(&grid, (1, 0), (1, 2), PathCosts::Uniform)
+            .expect("No path around the pillar!");
+        assert_eq!(around.len(), 4);
+        assert_eq!(pathfinder.cached_paths(), 1);
+        pathfinder.find_path(&grid, (1, 0), (1, 2), PathCosts::Uniform);
+        assert_eq!(pathfinder.cached_paths(), 1);
+
+        // Looks alone keep the cache
+        grid.get_mut(0, 0).unwrap().brightness = 0.5;
+        pathfinder.find_path(&grid, (0, 0), (2, 2), PathCosts::Uniform);
+        assert_eq!(pathfinder.cached_paths(), 2);
+
+        grid.collision_mut(1, 1).unwrap().collider = false;
+        let through = pathfinder
+            .find_path(&grid, (1, 0), (1, 2), PathCosts::Uniform)
+            .expect("No path once the pillar is gone!");
+        assert_eq!(through, vec![(1, 1), (1, 2)]);
+        assert_eq!(pathfinder.cached_paths(), 1);
+    }
+
+    #[test]
+    fn new_grids_invalidate_the_cache() {
+        let mut pathfinder = Pathfinder::default();
+        pathfinder.find_path(
+            &grid(&["..."]),
+            (0, 0),
+            (2, 0),
+            PathCosts::Uniform,
+        );
+        let walled = grid(&[".#."]);
+        assert_eq!(
+            pathfinder.find_path(&walled, (0, 0), (2, 0), PathCosts::Uniform),
+            None
+        );
+    }
+}
@@ -2,32 +2,111 @@ use bevy::{
     prelude::*, render::camera::Camera2d, sprite::collide_aabb::collide,
 };
 use bevy_inspector_egui::Inspectable;
+use rand::Rng;
 
 use crate::{
+    actions::{Action, ActionState},
     ascii::{spawn_ascii_sprite, AsciiSheet},
+    clock::GameClock,
     combat::CombatStats,
-    fadeout::create_fadeout,
+    encounter::{EncounterRateModifiers, EncounterTables, PendingEncounter},
+    equipment::{derive_stats, EffectiveStats, Equipment, StatBonus},
+    fadeout::{create_fadeout, create_warp_fadeout},
     graphics::{
         CharacterSheet, FacingDirection, FrameAnimation, PlayerGraphics,
     },
-    tilemap::{EncounterSpawner, TileCollider},
+    inventory::Inventory,
+    map::{Direction, MapData, MovementMode},
+    tilemap::{tile_translation, CurrentMap, TileGrid},
+    weather::CurrentWeather,
     GameState, TILE_SIZE,
 };
 
+/// Tiles walked after a fight before encounters can happen again.
+const GRACE_STEPS: usize = 8;
+/// Seconds a new direction has to be held after turning to it, before the
+/// player starts walking. Tapping it only turns in place.
+const TURN_SECONDS: f32 = 0.1;
+/// Tiles per second the player speeds up and slows down by, every second.
+const ACCELERATION: f32 = 30.0;
+const DECELERATION: f32 = 40.0;
+/// How far off a gap, in world units, the player still slides around the
+/// corner into it instead of stopping.
+const CORNER_TOLERANCE: f32 = TILE_SIZE * 0.4;
+const SPRINT_MULTIPLIER: f32 = 1.75;
+/// Stamina lost every second of sprinting and regained every second of not.
+const STAMINA_DRAIN: f32 = 1.0;
+const STAMINA_REGEN: f32 = 0.75;
+/// Fraction of stamina a drained player needs back before sprinting again.
+const RECOVERED_STAMINA: f32 = 0.5;
+const STAMINA_GLYPH: usize = 219;
+const STAMINA_COLOR: Color = Color::rgb(0.3, 0.9, 0.3);
+const EXHAUSTED_COLOR: Color = Color::rgb(0.9, 0.3, 0.2);
+
+/// Movement mode of maps that don't pick their own.
+pub struct MovementSettings {
+    pub mode: MovementMode,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        MovementSettings {
+            mode: MovementMode::Free,
+        }
+    }
+}
+
+/// State of the player walking tile by tile in grid movement.
+#[derive(Component, Default)]
+pub struct TileStepper {
+    step: Option<TileStep>,
+    /// Direction pressed during a step, walked in once it ends.
+    queued: Option<Direction>,
+    facing: Option<Direction>,
+    turning: f32,
+    /// Direction the player slides in while on ice.
+    sliding: Option<Direction>,
+}
+
+struct TileStep {
+    from: Vec2,
+    to: Vec2,
+    heading: Direction,
+    /// Fraction of the step walked, from 0 to 1.
+    progress: f32,
+}
+
 #[derive(Component, Default, Reflect)]
 #[reflect(Component)]
 pub struct EncounterTracker {
-    timer: Timer,
+    /// Distance walked in encounter zones since the last step.
+    distance: f32,
+    grace_steps: usize,
+    last_position: Vec3,
 }
 
 #[derive(Component, Inspectable)]
 pub struct Player {
+    /// Top speed in tiles per second.
     speed: f32,
+    /// World units per second in free movement.
+    velocity: Vec2,
     just_moved: bool,
+    /// Direction the player slides in while on ice.
+    sliding: Option<Vec2>,
+    sprinting: bool,
+    pub stamina: f32,
+    pub max_stamina: f32,
+    /// Drained of stamina, no sprinting until enough of it is back.
+    exhausted: bool,
     pub active: bool,
     pub exp: usize,
 }
 
+/// Bar over the player showing their stamina, hidden while it is full.
+#[derive(Component)]
+pub struct StaminaMeter;
+
 impl Player {
     pub fn give_exp(&mut self, exp: usize, stats: &mut CombatStats) -> bool {
         self.exp += exp;
@@ -41,6 +120,15 @@ impl Player {
         }
         false
     }
+
+    /// Tiles per second the player walks at on normal terrain.
+    fn move_speed(&self) -> f32 {
+        if self.sprinting {
+            self.speed * SPRINT_MULTIPLIER
+        } else {
+            self.speed
+        }
+    }
 }
 
 pub struct PlayerPlugin;
@@ -48,20 +136,35 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_system_set(
-            SystemSet::on_resume(GameState::Overworld).with_system(show_player),
+            SystemSet::on_resume(GameState::Overworld)
+                .with_system(show_player)
+                .with_system(start_encounter_grace),
         )
         .add_system_set(
             SystemSet::on_pause(GameState::Overworld).with_system(hide_player),
         )
         .add_system_set(
             SystemSet::on_update(GameState::Overworld)
-                .with_system(player_encounter_checking.after(player_movement))
-                .with_system(camera_follow.after(player_movement))
-                .with_system(player_movement),
+                .with_system(player_warp_checking.after(player_grid_movement))
+                .with_system(
+                    player_encounter_checking.after(player_warp_checking),
+                )
+                .with_system(camera_follow.after(player_grid_movement))
+                .with_system(
+                    terrain_damage
+                        .after(player_grid_movement)
+                        .after(derive_stats),
+                )
+                .with_system(player_sprint)
+                .with_system(player_movement.after(player_sprint))
+                .with_system(player_grid_movement.after(player_movement))
+                .with_system(player_stamina.after(player_grid_movement))
+                .with_system(draw_stamina_meter.after(player_stamina)),
         )
         .add_system_set(
             SystemSet::on_enter(GameState::Overworld).with_system(spawn_player),
-        );
+        )
+        .init_resource::<MovementSettings>();
     }
 }
 
@@ -100,35 +203,85 @@ fn hide_player(
     }
 }
 
+fn start_encounter_grace(mut tracker_query: Query<&mut EncounterTracker>) {
+    let mut encounter_tracker = tracker_query.single_mut();
+    encounter_tracker.distance = 0.0;
+    encounter_tracker.grace_steps = GRACE_STEPS;
+}
+
 fn player_encounter_checking(
     mut commands: Commands,
     mut player_query: Query<(&mut Player, &mut EncounterTracker, &Transform)>,
-    encounter_query: Query<
-        &Transform,
-        (With<EncounterSpawner>, Without<Player>),
-    >,
-    time: Res<Time>,
+    grid: Res<TileGrid>,
+    encounter_tables: Res<EncounterTables>,
+    rate_modifiers: Res<EncounterRateModifiers>,
+    clock: Res<GameClock>,
+    weather: Res<CurrentWeather>,
     ascii: Res<AsciiSheet>,
 ) {
     let (mut player, mut encounter_tracker, player_transform) =
         player_query.single_mut();
     let player_translation = player_transform.translation;
+    // Warps teleport the player, so never count more than a tile per frame
+    let moved = player_translation
+        .distance(encounter_tracker.last_position)
+        .min(TILE_SIZE);
+    encounter_tracker.last_position = player_translation;
 
-    if player.just_moved
-        && encounter_query.iter().any(|&transform| {
-            wall_collision_check(player_translation, transform.translation)
-        })
-    {
-        encounter_tracker.timer.tick(time.delta());
+    let zone = grid.neighborhood(player_translation).find_map(
+        |(translation, tile)| {
+            tile.encounter.as_ref().filter(|_| {
+                wall_collision_check(player_translation, translation)
+            })
+        },
+    );
 
-        if encounter_tracker.timer.just_finished() {
+    let zone = match zone.filter(|_| player.active && player.just_moved) {
+        Some(zone) => zone,
+        None => return,
+    };
+
+    encounter_tracker.distance += moved;
+    let rate = encounter_tables.rate(zone, clock.time_of_day(), weather.0)
+        * rate_modifiers.multiplier();
+    let rate = rate.clamp(0.0, 1.0) as f64;
+    while encounter_tracker.distance >= TILE_SIZE {
+        encounter_tracker.distance -= TILE_SIZE;
+        if encounter_tracker.grace_steps > 0 {
+            encounter_tracker.grace_steps -= 1;
+            continue;
+        }
+
+        if rand::thread_rng().gen_bool(rate) {
             println!("Change to combat");
+            commands.insert_resource(PendingEncounter { zone: zone.clone() });
             create_fadeout(&mut commands, Some(GameState::Combat), &ascii);
             player.active = false;
+            break;
         }
     }
 }
 
+fn player_warp_checking(
+    mut commands: Commands,
+    mut player_query: Query<(&mut Player, &Transform)>,
+    grid: Res<TileGrid>,
+    ascii: Res<AsciiSheet>,
+) {
+    let (mut player, player_transform) = player_query.single_mut();
+    if !player.just_moved {
+        return;
+    }
+
+    if let Some(warp) = grid
+        .tile_at(player_transform.translation)
+        .and_then(|tile| tile.warp.as_ref())
+    {
+        create_warp_fadeout(&mut commands, warp.clone(), &ascii);
+        player.active = false;
+    }
+}
+
 fn camera_follow(
     player_query: Query<&Transform, With<Player>>,
     mut camera_query: Query<&mut Transform, (With<Camera2d>, Without<Player>)>,
@@ -140,69 +293,443 @@ fn camera_follow(
     camera_transform.translation.y = player_transform.translation.y;
 }
 
+/// The mode of the current map, or else the global setting.
+fn movement_mode(
+    settings: &MovementSettings,
+    current: Option<&CurrentMap>,
+    maps: &Assets<MapData>,
+) -> MovementMode {
+    current
+        .and_then(|current| maps.get(&current.handle))
+        .and_then(|map| map.header.movement)
+        .unwrap_or(settings.mode)
+}
+
 fn player_movement(
     mut player_query: Query<(&mut Player, &mut Transform, &mut PlayerGraphics)>,
-    wall_query: Query<&Transform, (With<TileCollider>, Without<Player>)>,
-    keyboard: Res<Input<KeyCode>>,
+    grid: Res<TileGrid>,
+    actions: Res<ActionState>,
+    settings: Res<MovementSettings>,
+    current: Option<Res<CurrentMap>>,
+    maps: Res<Assets<MapData>>,
     time: Res<Time>,
 ) {
+    if movement_mode(&settings, current.as_deref(), &maps) != MovementMode::Free
+    {
+        return;
+    }
     let (mut player, mut transform, mut graphics) = player_query.single_mut();
     player.just_moved = false;
 
     if !player.active {
+        player.velocity = Vec2::ZERO;
         return;
     }
 
-    let mut delta_y = 0.0;
-    if keyboard.pressed(KeyCode::W) {
-        delta_y += time.delta_seconds() * player.speed * TILE_SIZE;
+    let speed = grid
+        .tile_at(transform.translation)
+        .map_or(1.0, |tile| tile.terrain.speed);
+    let top_speed = player.move_speed() * speed * TILE_SIZE;
+    let input = input_direction(&actions);
+
+    // Input is ignored while sliding over ice
+    let mut velocity = match player.sliding {
+        Some(direction) => direction * top_speed,
+        None if input == Vec2::ZERO => approach(
+            player.velocity,
+            Vec2::ZERO,
+            DECELERATION * TILE_SIZE * time.delta_seconds(),
+        ),
+        // Normalized, so diagonals are no faster
+        None => approach(
+            player.velocity,
+            input.normalize() * top_speed,
+            ACCELERATION * TILE_SIZE * time.delta_seconds(),
+        ),
+    };
+    let distance = velocity * time.delta_seconds();
+    let start = transform.translation;
+    let mut blocked = false;
+
+    // Each axis moves on its own, so moving diagonally into a wall slides
+    // along it
+    for axis in [Vec2::Y, Vec2::X] {
+        let delta = (distance * axis).extend(0.0);
+        if delta == Vec3::ZERO {
+            continue;
+        }
+        let heading = heading(delta.truncate());
+        let target = transform.translation + delta;
+        if !blocks_movement(&grid, transform.translation, target, heading) {
+            transform.translation = target;
+            continue;
+        }
+
+        // Only round corners the player isn't already steering around
+        let side = Vec2::new(axis.y, axis.x);
+        let nudge = if input * side == Vec2::ZERO {
+            round_corner(&grid, transform.translation, delta, side)
+        } else {
+            None
+        };
+        match nudge {
+            Some(nudge) => transform.translation += nudge,
+            None => {
+                velocity *= side;
+                blocked = true;
+            }
+        }
     }
-    if keyboard.pressed(KeyCode::S) {
-        delta_y -= time.delta_seconds() * player.speed * TILE_SIZE;
+
+    player.velocity = velocity;
+    player.just_moved = transform.translation != start;
+    if player.just_moved && input != Vec2::ZERO {
+        graphics.facing = facing(heading(input));
     }
 
-    let mut delta_x = 0.0;
-    if keyboard.pressed(KeyCode::A) {
-        delta_x -= time.delta_seconds() * player.speed * TILE_SIZE;
+    let on_ice = grid
+        .tile_at(transform.translation)
+        .map_or(false, |tile| tile.terrain.slippery);
+    player.sliding = if on_ice && player.just_moved && !blocked {
+        Some(velocity.normalize_or_zero())
+    } else {
+        None
+    };
+}
+
+/// Held movement actions as a direction, not normalized.
+fn input_direction(actions: &ActionState) -> Vec2 {
+    let mut direction = Vec2::ZERO;
+    if actions.pressed(Action::MoveUp) {
+        direction.y += 1.0;
     }
-    if keyboard.pressed(KeyCode::D) {
-        delta_x += time.delta_seconds() * player.speed * TILE_SIZE;
+    if actions.pressed(Action::MoveDown) {
+        direction.y -= 1.0;
     }
+    if actions.pressed(Action::MoveLeft) {
+        direction.x -= 1.0;
+    }
+    if actions.pressed(Action::MoveRight) {
+        direction.x += 1.0;
+    }
+    direction
+}
 
-    let target = transform.translation + Vec3::new(0.0, delta_y, 0.0);
-    if !wall_query
-        .iter()
-        .any(|&transform| wall_collision_check(target, transform.translation))
+/// Moves `velocity` towards `target` by at most `change`.
+fn approach(velocity: Vec2, target: Vec2, change: f32) -> Vec2 {
+    let difference = target - velocity;
+    if difference.length() <= change {
+        target
+    } else {
+        velocity + difference.normalize() * change
+    }
+}
+
+/// Direction of the larger axis of `delta`, horizontal on ties.
+fn heading(delta: Vec2) -> Direction {
+    if delta.x.abs() >= delta.y.abs() {
+        if delta.x > 0.0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    } else if delta.y > 0.0 {
+        Direction::Up
+    } else {
+        Direction::Down
+    }
+}
+
+/// When the move by `delta` is blocked only by the corner of a tile, the
+/// nudge along `side` that lines the player up with the gap next to it, no
+/// longer than the move itself.
+fn round_corner(
+    grid: &TileGrid,
+    from: Vec3,
+    delta: Vec3,
+    side: Vec2,
+) -> Option<Vec3> {
+    let side = side.extend(0.0);
+    let offset = from.dot(side);
+    let nearest = (offset / TILE_SIZE).round() * TILE_SIZE;
+    let other = nearest + (offset - nearest).signum() * TILE_SIZE;
+    let step = delta.length();
+
+    [nearest, other].into_iter().find_map(|center| {
+        let shift = center - offset;
+        let aligned = from + side * shift;
+        let free = shift.abs() <= CORNER_TOLERANCE
+            && !blocks_movement(
+                grid,
+                from,
+                aligned,
+                heading(side.truncate() * shift),
+            )
+            && !blocks_movement(
+                grid,
+                aligned,
+                aligned + delta,
+                heading(delta.truncate()),
+            );
+        free.then(|| side * shift.clamp(-step, step))
+    })
+}
+
+fn player_grid_movement(
+    mut player_query: Query<(
+        &mut Player,
+        &mut TileStepper,
+        &mut Transform,
+        &mut PlayerGraphics,
+    )>,
+    grid: Res<TileGrid>,
+    actions: Res<ActionState>,
+    settings: Res<MovementSettings>,
+    current: Option<Res<CurrentMap>>,
+    maps: Res<Assets<MapData>>,
+    time: Res<Time>,
+) {
+    if movement_mode(&settings, current.as_deref(), &maps) != MovementMode::Grid
     {
-        if delta_y != 0.0 {
-            player.just_moved = true;
-            if delta_y > 0.0 {
-                graphics.facing = FacingDirection::Up;
-            } else if delta_y < 0.0 {
-                graphics.facing = FacingDirection::Down;
-            }
+        return;
+    }
+    let (mut player, mut stepper, mut transform, mut graphics) =
+        player_query.single_mut();
+    let z = transform.translation.z;
+    player.just_moved = false;
+
+    // Whatever stopped the player (a warp, a fight, a textbox) happened on
+    // the tile they were stepping onto, so finish the step right away
+    if !player.active {
+        if let Some(step) = stepper.step.take() {
+            transform.translation = step.to.extend(z);
         }
-        transform.translation = target;
+        stepper.queued = None;
+        return;
     }
 
-    let target = transform.translation + Vec3::new(delta_x, 0.0, 0.0);
-    if !wall_query
+    let moves = [
+        (Action::MoveUp, Direction::Up),
+        (Action::MoveDown, Direction::Down),
+        (Action::MoveLeft, Direction::Left),
+        (Action::MoveRight, Direction::Right),
+    ];
+    let held = moves
         .iter()
-        .any(|&transform| wall_collision_check(target, transform.translation))
-    {
-        if delta_x != 0.0 {
-            player.just_moved = true;
-            if delta_x > 0.0 {
-                graphics.facing = FacingDirection::Right;
-            } else if delta_x < 0.0 {
-                graphics.facing = FacingDirection::Left;
+        .find(|(action, _)| actions.pressed(*action))
+        .map(|(_, direction)| *direction);
+    if stepper.step.is_some() {
+        if let Some((_, direction)) = moves
+            .iter()
+            .find(|(action, _)| actions.just_pressed(*action))
+        {
+            stepper.queued = Some(*direction);
+        }
+    }
+
+    let speed = grid
+        .tile_at(transform.translation)
+        .map_or(1.0, |tile| tile.terrain.speed);
+    // In tiles, any left after a step carries over into the next one so
+    // walking doesn't stutter between tiles
+    let mut travel = time.delta_seconds() * player.move_speed() * speed;
+    let mut walking = false;
+    while travel > 0.0 {
+        if stepper.step.is_none() {
+            // Input is ignored while sliding over ice
+            let direction = match stepper
+                .sliding
+                .or_else(|| stepper.queued.take())
+                .or(held)
+            {
+                Some(direction) => direction,
+                None => {
+                    stepper.turning = 0.0;
+                    break;
+                }
+            };
+            let standing = !walking && stepper.sliding.is_none();
+            if standing && stepper.facing != Some(direction) {
+                stepper.facing = Some(direction);
+                stepper.turning = TURN_SECONDS;
+                graphics.facing = facing(direction);
+                break;
+            }
+            if standing && stepper.turning > 0.0 {
+                stepper.turning -= time.delta_seconds();
+                break;
+            }
+
+            let (x, y) = TileGrid::tile_coords(transform.translation);
+            let (dx, dy) = match direction {
+                Direction::Up => (0, -1),
+                Direction::Down => (0, 1),
+                Direction::Left => (-1, 0),
+                Direction::Right => (1, 0),
+            };
+            if stepper.facing != Some(direction) {
+                stepper.facing = Some(direction);
+                graphics.facing = facing(direction);
             }
+            // Bumping into something still turns towards it
+            if blocks_step(&grid, (x + dx, y + dy), direction) {
+                stepper.sliding = None;
+                break;
+            }
+            stepper.step = Some(TileStep {
+                from: transform.translation.truncate(),
+                to: tile_translation((x + dx) as usize, (y + dy) as usize),
+                heading: direction,
+                progress: 0.0,
+            });
+        }
+
+        let step = stepper.step.as_mut().expect("No step to walk!");
+        player.just_moved = true;
+        if step.progress + travel < 1.0 {
+            step.progress += travel;
+            transform.translation =
+                step.from.lerp(step.to, step.progress).extend(z);
+            break;
+        }
+        travel -= 1.0 - step.progress;
+        transform.translation = step.to.extend(z);
+        let heading = step.heading;
+        stepper.step = None;
+        walking = true;
+
+        let on_ice = grid
+            .tile_at(transform.translation)
+            .map_or(false, |tile| tile.terrain.slippery);
+        stepper.sliding = if on_ice { Some(heading) } else { None };
+    }
+}
+
+fn facing(direction: Direction) -> FacingDirection {
+    match direction {
+        Direction::Up => FacingDirection::Up,
+        Direction::Down => FacingDirection::Down,
+        Direction::Left => FacingDirection::Left,
+        Direction::Right => FacingDirection::Right,
+    }
+}
+
+/// Whether a grid step onto tile `(x, y)` is blocked, like
+/// [`blocks_movement`] but off the map is blocked too.
+fn blocks_step(
+    grid: &TileGrid,
+    (x, y): (i32, i32),
+    heading: Direction,
+) -> bool {
+    grid.get(x, y).map_or(true, |tile| {
+        tile.collider
+            || tile
+                .terrain
+                .one_way
+                .map_or(false, |one_way| one_way != heading)
+    })
+}
+
+/// Colliders always block, one-way tiles only block entering them against
+/// their direction.
+fn blocks_movement(
+    grid: &TileGrid,
+    from: Vec3,
+    to: Vec3,
+    heading: Direction,
+) -> bool {
+    grid.neighborhood(to).any(|(tile_translation, tile)| {
+        let entering = wall_collision_check(to, tile_translation)
+            && !wall_collision_check(from, tile_translation);
+        let wrong_way = tile
+            .terrain
+            .one_way
+            .map_or(false, |one_way| entering && one_way != heading);
+        (tile.collider && wall_collision_check(to, tile_translation))
+            || wrong_way
+    })
+}
+
+/// Whether this frame's movement is a sprint, decided before moving.
+fn player_sprint(
+    mut player_query: Query<&mut Player>,
+    actions: Res<ActionState>,
+) {
+    let mut player = player_query.single_mut();
+    let moving = input_direction(&actions) != Vec2::ZERO;
+    player.sprinting = player.active
+        && moving
+        && !player.exhausted
+        && actions.pressed(Action::Sprint);
+}
+
+/// Drains stamina for sprints that moved the player, running into a wall
+/// costs nothing, and regains it otherwise.
+fn player_stamina(mut player_query: Query<&mut Player>, time: Res<Time>) {
+    let mut player = player_query.single_mut();
+    let seconds = time.delta_seconds();
+    if player.sprinting && player.just_moved {
+        player.stamina = (player.stamina - STAMINA_DRAIN * seconds).max(0.0);
+        player.exhausted = player.stamina == 0.0;
+    } else {
+        player.stamina =
+            (player.stamina + STAMINA_REGEN * seconds).min(player.max_stamina);
+        if player.stamina >= player.max_stamina * RECOVERED_STAMINA {
+            player.exhausted = false;
         }
-        transform.translation = target;
     }
 }
 
-fn wall_collision_check(
+fn draw_stamina_meter(
+    player_query: Query<&Player>,
+    mut meter_query: Query<
+        (&mut Transform, &mut Visibility, &mut TextureAtlasSprite),
+        With<StaminaMeter>,
+    >,
+) {
+    let player = player_query.single();
+    let (mut transform, mut visibility, mut sprite) = meter_query.single_mut();
+    let fraction = (player.stamina / player.max_stamina).clamp(0.0, 1.0);
+    visibility.is_visible = player.active && fraction < 1.0;
+    // Shrinks towards its left end
+    transform.scale.x = fraction;
+    transform.translation.x = -(1.0 - fraction) * TILE_SIZE / 2.0;
+    sprite.color = if player.exhausted {
+        EXHAUSTED_COLOR
+    } else {
+        STAMINA_COLOR
+    };
+}
+
+/// Damaging terrain hurts once for every second spent on it.
+fn terrain_damage(
+    mut player_query: Query<(
+        &Player,
+        &Transform,
+        &mut CombatStats,
+        &EffectiveStats,
+    )>,
+    grid: Res<TileGrid>,
+    time: Res<Time>,
+    mut elapsed: Local<f32>,
+) {
+    let (player, transform, mut stats, effective) = player_query.single_mut();
+    let damage = grid
+        .tile_at(transform.translation)
+        .map_or(0, |tile| tile.terrain.damage);
+    if !player.active || damage == 0 {
+        *elapsed = 0.0;
+        return;
+    }
+
+    *elapsed += time.delta_seconds();
+    if *elapsed >= 1.0 {
+        *elapsed -= 1.0;
+        stats.health = (stats.health - damage).clamp(1, effective.max_health);
+    }
+}
+
+pub fn wall_collision_check(
     target_player_pos: Vec3,
     wall_translation: Vec3,
 ) -> bool {
@@ -215,8 +742,40 @@ fn wall_collision_check(
     collision.is_some()
 }
 
-pub fn spawn_player(mut commands: Commands, characters: Res<CharacterSheet>) {
-    let player = commands
+pub fn spawn_player(
+    mut commands: Commands,
+    characters: Res<CharacterSheet>,
+    ascii: Res<AsciiSheet>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapData>>,
+) {
+    let map = maps
+        .get(&current_map.handle)
+        .expect("Start map is not loaded!");
+    let (x, y) = map.arrival().unwrap_or_else(|| {
+        panic!("Map {} has no player start or spawn point!", current_map.id)
+    });
+    let stats = CombatStats {
+        health: 10,
+        max_health: 10,
+        attack: 2,
+        defense: 1,
+    };
+    let start = tile_translation(x, y);
+    let meter = spawn_ascii_sprite(
+        &mut commands,
+        &ascii,
+        STAMINA_GLYPH,
+        STAMINA_COLOR,
+        Vec3::new(0.0, TILE_SIZE * 0.6, 1.0),
+        Vec3::new(1.0, 0.15, 1.0),
+    );
+    commands
+        .entity(meter)
+        .insert(Name::new("Stamina"))
+        .insert(StaminaMeter);
+
+    commands
         .spawn_bundle(SpriteSheetBundle {
             sprite: TextureAtlasSprite {
                 index: characters.player_down[0],
@@ -224,11 +783,7 @@ pub fn spawn_player(mut commands: Commands, characters: Res<CharacterSheet>) {
                 ..default()
             },
             texture_atlas: characters.handle.clone(),
-            transform: Transform::from_xyz(
-                2.0 * TILE_SIZE,
-                -2.0 * TILE_SIZE,
-                900.0,
-            ),
+            transform: Transform::from_xyz(start.x, start.y, 900.0),
             ..default()
         })
         .insert(FrameAnimation {
@@ -242,18 +797,27 @@ pub fn spawn_player(mut commands: Commands, characters: Res<CharacterSheet>) {
         .insert(Name::new("Player"))
         .insert(Player {
             speed: 3.0,
+            velocity: Vec2::ZERO,
             just_moved: false,
+            sliding: None,
+            sprinting: false,
+            stamina: 3.0,
+            max_stamina: 3.0,
+            exhausted: false,
             active: true,
             exp: 0,
         })
-        .insert(CombatStats {
-            health: 10,
-            max_health: 10,
-            attack: 2,
-            defense: 1,
-        })
+        .insert(EffectiveStats::new(&stats, StatBonus::default()))
+        .insert(stats)
         .insert(EncounterTracker {
-            timer: Timer::from_seconds(1.0, true),
+            last_position: start.extend(900.0),
+            ..default()
+        })
+        .insert(TileStepper {
+            facing: Some(Direction::Down),
+            ..default()
         })
-        .id();
+        .insert(Equipment::default())
+        .insert(Inventory::default())
+        .add_child(meter);
 }
@@ -1,6 +1,9 @@
 use bevy::{prelude::*, ui::FocusPolicy};
 
-use crate::{ascii::AsciiSheet, fadeout::create_fadeout, GameState};
+use crate::{
+    ascii::AsciiSheet, fadeout::create_fadeout, map::MapData,
+    tilemap::CurrentMap, GameState,
+};
 
 struct UiAssets {
     font: Handle<Font>,
@@ -45,14 +48,19 @@ fn handle_start_button(
     mut image_query: Query<&mut UiImage>,
     ui_assets: Res<UiAssets>,
     ascii: Res<AsciiSheet>,
+    current_map: Res<CurrentMap>,
+    maps: Res<Assets<MapData>>,
 ) {
+    // The player is placed from the start map, so wait until it has loaded
+    let map_loaded = maps.get(&current_map.handle).is_some();
+
     for (children, mut active, interaction) in interaction_query.iter_mut() {
         let child = children.iter().next().unwrap();
         let mut image = image_query.get_mut(*child).unwrap();
 
         match interaction {
             Interaction::Clicked => {
-                if active.0 {
+                if active.0 && map_loaded {
                     active.0 = false;
                     image.0 = ui_assets.button_pressed.clone();
                     create_fadeout(&mut commands, Some(GameState::Overworld), &ascii)
@@ -0,0 +1,1056 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    utils::BoxedFuture,
+};
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+
+use crate::{
+    interact::{Interactable, Toggle},
+    map::{
+        log_diagnostics, MapData, MapEntity, MapHeader, TileAnimation, TileDef,
+    },
+    npc::Npc,
+    tilemap::Warp,
+    validation::{check_map_data, Diagnostic, MapError, Position, Severity},
+};
+
+/// Atlas index of tiles no layer draws anything on.
+const EMPTY_INDEX: usize = ' ' as usize;
+/// Glyphs of converted maps come from the unicode private use area, the
+/// legend is generated so they never mean anything on their own.
+const FIRST_GLYPH: u32 = 0xE000;
+/// Tiled keeps the flip flags of a tile in the top bits of its gid.
+const GID_MASK: u32 = 0x1FFF_FFFF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TiledFormat {
+    Tmx,
+    Tmj,
+}
+
+impl TiledFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "tmx" => Some(TiledFormat::Tmx),
+            "tmj" => Some(TiledFormat::Tmj),
+            _ => None,
+        }
+    }
+}
+
+type Properties = HashMap<String, String>;
+
+/// The parts of a Tiled map the game uses, read from either format.
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_width: f32,
+    tile_height: f32,
+    properties: Properties,
+    /// Where the map properties are, for converter errors.
+    property_positions: HashMap<String, Position>,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Layer>,
+}
+
+impl TiledMap {
+    fn property_error(&self, key: &str, message: String) -> Diagnostic {
+        let position = self.property_positions.get(key).copied();
+        error(position.unwrap_or(Position::File), message)
+    }
+}
+
+struct Tileset {
+    first_gid: u32,
+    /// Custom properties by local tile id.
+    tiles: HashMap<u32, Properties>,
+    animations: HashMap<u32, TileAnimation>,
+}
+
+enum Layer {
+    /// Gids and where the layer is.
+    Tiles(Vec<u32>, Position),
+    Objects(Vec<Object>),
+}
+
+struct Object {
+    name: String,
+    kind: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    /// Tile objects are positioned by their bottom left corner.
+    tile: bool,
+    properties: Properties,
+}
+
+/// Parses a Tiled map into the same legend driven [`MapData`] map files use.
+/// `name` is used unless the map has a `name` property.
+pub fn parse_tiled(
+    text: &str,
+    format: TiledFormat,
+    name: &str,
+) -> Result<MapData, Diagnostic> {
+    let tiled = match format {
+        TiledFormat::Tmx => read_tmx(text)?,
+        TiledFormat::Tmj => read_tmj(text)?,
+    };
+    convert(&tiled, name)
+}
+
+/// Parses and validates a Tiled map, see [`check_map_data`].
+pub fn check_tiled_map(
+    text: &str,
+    format: TiledFormat,
+    name: &str,
+) -> (Option<MapData>, Vec<Diagnostic>) {
+    match parse_tiled(text, format, name) {
+        Ok(map) => check_map_data(map),
+        Err(diagnostic) => (None, vec![diagnostic]),
+    }
+}
+
+fn error(position: Position, message: String) -> Diagnostic {
+    Diagnostic {
+        severity: Severity::Error,
+        position,
+        message,
+    }
+}
+
+/// Errors found while converting are positioned by tile, like the ones
+/// found validating the converted map.
+fn tile_error((x, y): (usize, usize), message: String) -> Diagnostic {
+    error(Position::Tile { x, y }, message)
+}
+
+fn convert(tiled: &TiledMap, name: &str) -> Result<MapData, Diagnostic> {
+    let empty = TileDef {
+        index: Some(EMPTY_INDEX),
+        ..TileDef::default()
+    };
+    let mut tiles = vec![empty; tiled.width * tiled.height];
+
+    for layer in tiled.layers.iter() {
+        match layer {
+            Layer::Tiles(gids, position) => {
+                if gids.len() != tiles.len() {
+                    return Err(error(
+                        *position,
+                        format!(
+                            "tile layer has {} tiles, expected {}",
+                            gids.len(),
+                            tiles.len()
+                        ),
+                    ));
+                }
+                for (i, gid) in gids.iter().enumerate() {
+                    let position = (i % tiled.width, i / tiled.width);
+                    apply_tile(tiled, &mut tiles[i], gid & GID_MASK, position)?;
+                }
+            }
+            Layer::Objects(objects) => {
+                for object in objects.iter() {
+                    apply_object(tiled, &mut tiles, object)?;
+                }
+            }
+        }
+    }
+
+    let mut defs: Vec<TileDef> = Vec::new();
+    let mut rows = Vec::new();
+    for row in tiles.chunks(tiled.width.max(1)) {
+        let mut glyphs = Vec::new();
+        for def in row.iter() {
+            let index = defs.iter().position(|known| known == def);
+            let index = index.unwrap_or_else(|| {
+                defs.push(def.clone());
+                defs.len() - 1
+            });
+            glyphs.push(glyph(index));
+        }
+        rows.push(glyphs);
+    }
+
+    let fog_of_war = match tiled.properties.get("fog_of_war") {
+        Some(radius) => Some(radius.parse().map_err(|_| {
+            tiled.property_error(
+                "fog_of_war",
+                format!("fog_of_war '{}' is not a number", radius),
+            )
+        })?),
+        None => None,
+    };
+    let movement = match tiled.properties.get("movement") {
+        Some(movement) => Some(ron::from_str(movement).map_err(|_| {
+            tiled.property_error(
+                "movement",
+                format!("unknown movement mode '{}'", movement),
+            )
+        })?),
+        None => None,
+    };
+    let weather = match tiled.properties.get("weather") {
+        Some(weather) => Some(ron::from_str(weather).map_err(|_| {
+            tiled.property_error(
+                "weather",
+                format!("unknown weather '{}'", weather),
+            )
+        })?),
+        None => None,
+    };
+
+    Ok(MapData {
+        header: MapHeader {
+            name: tiled
+                .properties
+                .get("name")
+                .cloned()
+                .unwrap_or_else(|| name.to_string()),
+            legend: defs
+                .into_iter()
+                .enumerate()
+                .map(|(index, def)| (glyph(index), def))
+                .collect(),
+            fog_of_war,
+            indoors: tiled
+                .properties
+                .get("indoors")
+                .map_or(false, |indoors| indoors == "true"),
+            weather,
+            movement,
+        },
+        rows,
+        layout_line: None,
+    })
+}
+
+fn glyph(index: usize) -> char {
+    char::from_u32(FIRST_GLYPH + index as u32)
+        .expect("Too many different tiles!")
+}
+
+fn apply_tile(
+    tiled: &TiledMap,
+    def: &mut TileDef,
+    gid: u32,
+    position: (usize, usize),
+) -> Result<(), Diagnostic> {
+    if gid == 0 {
+        return Ok(());
+    }
+    let tileset = tiled
+        .tilesets
+        .iter()
+        .filter(|tileset| tileset.first_gid <= gid)
+        .max_by_key(|tileset| tileset.first_gid)
+        .ok_or_else(|| {
+            tile_error(position, format!("tile {} is in no tileset", gid))
+        })?;
+    let id = gid - tileset.first_gid;
+
+    // The tileset is expected to be the ascii sheet, so ids are atlas indices
+    def.index = Some(id as usize);
+    def.color = TileDef::default().color;
+    def.animation = tileset.animations.get(&id).cloned();
+    match tileset.tiles.get(&id) {
+        Some(properties) => apply_properties(def, properties, position),
+        None => Ok(()),
+    }
+}
+
+/// Custom properties tiles and objects share.
+fn apply_properties(
+    def: &mut TileDef,
+    properties: &Properties,
+    position: (usize, usize),
+) -> Result<(), Diagnostic> {
+    for (key, value) in properties.iter() {
+        match key.as_str() {
+            "collider" => def.collider |= value == "true",
+            "color" => {
+                def.color = parse_color(value).ok_or_else(|| {
+                    tile_error(position, format!("invalid color '{}'", value))
+                })?;
+            }
+            "encounter" => def.encounter = Some(value.clone()),
+            "speed" => def.terrain.speed = parse(value, position)?,
+            "damage" => def.terrain.damage = parse(value, position)?,
+            "slippery" => def.terrain.slippery |= value == "true",
+            "one_way" => {
+                let direction = ron::from_str(value).map_err(|_| {
+                    tile_error(
+                        position,
+                        format!("invalid direction '{}'", value),
+                    )
+                })?;
+                def.terrain.one_way = Some(direction);
+            }
+            "npc" => {
+                let npc: Npc = ron::from_str(value).map_err(|_| {
+                    tile_error(position, format!("unknown npc '{}'", value))
+                })?;
+                def.spawn = Some(MapEntity::Npc(npc));
+                def.collider = true;
+            }
+            "light" => def.light = Some(parse(value, position)?),
+            "available" => {
+                let time = ron::from_str(value).map_err(|_| {
+                    tile_error(
+                        position,
+                        format!("invalid time of day '{}'", value),
+                    )
+                })?;
+                def.available = Some(time);
+            }
+            "weather" => {
+                let weather = ron::from_str(value).map_err(|_| {
+                    tile_error(position, format!("unknown weather '{}'", value))
+                })?;
+                def.weather = Some(weather);
+            }
+            // Artists may keep their own notes on tiles
+            _ => {}
+        }
+    }
+    if let Some(switch) = properties.get("toggle") {
+        let index = match properties.get("toggle_index") {
+            Some(index) => parse(index, position)?,
+            None => EMPTY_INDEX,
+        };
+        def.toggle = Some(Toggle {
+            switch: switch.clone(),
+            index,
+        });
+    }
+    Ok(())
+}
+
+fn apply_object(
+    tiled: &TiledMap,
+    tiles: &mut [TileDef],
+    object: &Object,
+) -> Result<(), Diagnostic> {
+    let y = if object.tile {
+        object.y - tiled.tile_height
+    } else {
+        object.y
+    };
+    let left = (object.x / tiled.tile_width).floor() as i64;
+    let top = (y / tiled.tile_height).floor() as i64;
+    let right = ((object.x + object.width) / tiled.tile_width).ceil() as i64;
+    let bottom = ((y + object.height) / tiled.tile_height).ceil() as i64;
+    // Point objects still cover the tile they are on
+    let right = right.max(left + 1).min(tiled.width as i64);
+    let bottom = bottom.max(top + 1).min(tiled.height as i64);
+    let (left, top) = (left.max(0), top.max(0));
+
+    let position = (left as usize, top as usize);
+    if left >= right || top >= bottom {
+        return Err(tile_error(
+            position,
+            format!("object '{}' is outside the map", object.name),
+        ));
+    }
+
+    let property = |key: &str| {
+        object.properties.get(key).cloned().ok_or_else(|| {
+            tile_error(
+                position,
+                format!(
+                    "{} '{}' has no '{}' property",
+                    object.kind, object.name, key
+                ),
+            )
+        })
+    };
+    let (warp, interact) = match object.kind.as_str() {
+        "warp" => {
+            let warp = Warp {
+                map: property("map")?,
+                spawn: property("spawn")?,
+            };
+            (Some(warp), None)
+        }
+        "chest" => {
+            let count = match object.properties.get("count") {
+                Some(count) => parse(count, position)?,
+                None => 1,
+            };
+            let item = property("item")?;
+            (None, Some(Interactable::Chest { item, count }))
+        }
+        "sign" => (None, Some(Interactable::Sign(property("text")?))),
+        "door" => {
+            let key = property("key")?;
+            (None, Some(Interactable::Door { key }))
+        }
+        "spawn_point" | "switch" if object.name.is_empty() => {
+            return Err(tile_error(
+                position,
+                format!("{} has no name", object.kind.replace('_', " ")),
+            ));
+        }
+        "switch" => (None, Some(Interactable::Switch(object.name.clone()))),
+        "" | "spawn_point" | "player_start" => (None, None),
+        kind => {
+            return Err(tile_error(
+                position,
+                format!("unknown object type '{}'", kind),
+            ));
+        }
+    };
+
+    for y in top as usize..bottom as usize {
+        for x in left as usize..right as usize {
+            let def = &mut tiles[y * tiled.width + x];
+            match object.kind.as_str() {
+                "warp" => def.warp = warp.clone(),
+                "spawn_point" => def.spawn_point = Some(object.name.clone()),
+                "player_start" => def.player_start = true,
+                _ => {}
+            }
+            if interact.is_some() {
+                def.interact = interact.clone();
+                def.collider = true;
+            }
+            apply_properties(def, &object.properties, (x, y))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse<T: FromStr>(
+    value: &str,
+    position: (usize, usize),
+) -> Result<T, Diagnostic> {
+    value.parse().map_err(|_| {
+        tile_error(position, format!("'{}' is not a number", value))
+    })
+}
+
+/// Tiled animations give every frame its own duration, the first one is used
+/// for all of them.
+fn animation(frames: &[(usize, u32)]) -> Option<TileAnimation> {
+    let &(_, duration) = frames.first()?;
+    Some(TileAnimation {
+        frame_time: duration as f32 / 1000.0,
+        frames: frames.iter().map(|&(tile, _)| tile).collect(),
+        colors: Vec::new(),
+    })
+}
+
+/// Tiled colors are `#rrggbb` or `#aarrggbb`, alpha is ignored.
+fn parse_color(value: &str) -> Option<(f32, f32, f32)> {
+    let hex = value.strip_prefix('#')?;
+    let hex = match hex.len() {
+        8 => &hex[2..],
+        6 => hex,
+        _ => return None,
+    };
+    let channel = |i: usize| {
+        let channel = u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()?;
+        Some(channel as f32 / 255.0)
+    };
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[derive(Deserialize)]
+struct TmjMap {
+    width: usize,
+    height: usize,
+    tilewidth: f32,
+    tileheight: f32,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+    #[serde(default)]
+    tilesets: Vec<TmjTileset>,
+    layers: Vec<TmjLayer>,
+}
+
+#[derive(Deserialize)]
+struct TmjProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct TmjTileset {
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TmjTile>,
+}
+
+#[derive(Deserialize)]
+struct TmjTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+    #[serde(default)]
+    animation: Vec<TmjFrame>,
+}
+
+#[derive(Deserialize)]
+struct TmjFrame {
+    tileid: usize,
+    duration: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TmjLayer {
+    TileLayer {
+        data: TmjData,
+    },
+    ObjectGroup {
+        objects: Vec<TmjObject>,
+    },
+    Group {
+        layers: Vec<TmjLayer>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TmjData {
+    Gids(Vec<u32>),
+    Encoded(String),
+}
+
+#[derive(Deserialize)]
+struct TmjObject {
+    #[serde(default)]
+    name: String,
+    /// Called `class` by Tiled 1.9.
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<TmjProperty>,
+}
+
+fn read_tmj(text: &str) -> Result<TiledMap, Diagnostic> {
+    let map: TmjMap = serde_json::from_str(text).map_err(|err| {
+        // Drop the position serde appends, diagnostics show it already
+        let message = err.to_string();
+        let message = message.split(" at line ").next().unwrap_or_default();
+        let position = Position::Text {
+            line: err.line(),
+            column: err.column(),
+        };
+        error(position, message.to_string())
+    })?;
+    // Values parsed by serde keep no position, so the errors below can only
+    // name the file
+    if map.infinite {
+        return Err(error(
+            Position::File,
+            "infinite maps are not supported".to_string(),
+        ));
+    }
+
+    let mut tilesets = Vec::new();
+    for tileset in map.tilesets {
+        if let Some(source) = tileset.source {
+            return Err(external_tileset(Position::File, &source));
+        }
+        let mut tiles = HashMap::new();
+        let mut animations = HashMap::new();
+        for tile in tileset.tiles {
+            let frames: Vec<(usize, u32)> = tile
+                .animation
+                .iter()
+                .map(|frame| (frame.tileid, frame.duration))
+                .collect();
+            if let Some(animation) = animation(&frames) {
+                animations.insert(tile.id, animation);
+            }
+            tiles.insert(tile.id, tmj_properties(tile.properties));
+        }
+        tilesets.push(Tileset {
+            first_gid: tileset.firstgid,
+            tiles,
+            animations,
+        });
+    }
+
+    let mut layers = Vec::new();
+    tmj_layers(map.layers, &mut layers)?;
+
+    Ok(TiledMap {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        properties: tmj_properties(map.properties),
+        property_positions: HashMap::new(),
+        tilesets,
+        layers,
+    })
+}
+
+fn tmj_properties(properties: Vec<TmjProperty>) -> Properties {
+    properties
+        .into_iter()
+        .map(|property| {
+            let value = match property.value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            (property.name, value)
+        })
+        .collect()
+}
+
+/// Flattens group layers, keeping the drawing order.
+fn tmj_layers(
+    source: Vec<TmjLayer>,
+    layers: &mut Vec<Layer>,
+) -> Result<(), Diagnostic> {
+    for layer in source {
+        match layer {
+            TmjLayer::TileLayer {
+                data: TmjData::Gids(gids),
+            } => layers.push(Layer::Tiles(gids, Position::File)),
+            TmjLayer::TileLayer {
+                data: TmjData::Encoded(_),
+            } => return Err(encoded_layer(Position::File)),
+            TmjLayer::ObjectGroup { objects } => {
+                let objects = objects
+                    .into_iter()
+                    .map(|object| Object {
+                        name: object.name,
+                        kind: if object.kind.is_empty() {
+                            object.class
+                        } else {
+                            object.kind
+                        },
+                        x: object.x,
+                        y: object.y,
+                        width: object.width,
+                        height: object.height,
+                        tile: object.gid.is_some(),
+                        properties: tmj_properties(object.properties),
+                    })
+                    .collect();
+                layers.push(Layer::Objects(objects));
+            }
+            TmjLayer::Group { layers: group } => tmj_layers(group, layers)?,
+            TmjLayer::Other => {}
+        }
+    }
+    Ok(())
+}
+
+fn external_tileset(position: Position, source: &str) -> Diagnostic {
+    error(
+        position,
+        format!(
+            "external tileset '{}' is not supported, embed it in the map",
+            source
+        ),
+    )
+}
+
+fn encoded_layer(position: Position) -> Diagnostic {
+    error(position, "only CSV tile layer data is supported".to_string())
+}
+
+fn read_tmx(text: &str) -> Result<TiledMap, Diagnostic> {
+    let doc = Document::parse(text).map_err(|err| {
+        let position = Position::Text {
+            line: err.pos().row as usize,
+            column: err.pos().col as usize,
+        };
+        error(position, err.to_string())
+    })?;
+    let map = doc.root_element();
+    if map.tag_name().name() != "map" {
+        return Err(xml_error(
+            &doc,
+            map,
+            "expected a <map> element".to_string(),
+        ));
+    }
+    if map.attribute("infinite") == Some("1") {
+        return Err(xml_error(
+            &doc,
+            map,
+            "infinite maps are not supported".to_string(),
+        ));
+    }
+
+    let mut tilesets = Vec::new();
+    for tileset in children(map, "tileset") {
+        if let Some(source) = tileset.attribute("source") {
+            return Err(external_tileset(xml_position(&doc, tileset), source));
+        }
+        let mut tiles = HashMap::new();
+        let mut animations = HashMap::new();
+        for tile in children(tileset, "tile") {
+            let id = attribute(&doc, tile, "id")?;
+            let mut frames = Vec::new();
+            for frame in children(tile, "animation")
+                .flat_map(|animation| children(animation, "frame"))
+            {
+                frames.push((
+                    attribute(&doc, frame, "tileid")?,
+                    attribute(&doc, frame, "duration")?,
+                ));
+            }
+            if let Some(animation) = animation(&frames) {
+                animations.insert(id, animation);
+            }
+            tiles.insert(id, tmx_properties(tile));
+        }
+        tilesets.push(Tileset {
+            first_gid: attribute(&doc, tileset, "firstgid")?,
+            tiles,
+            animations,
+        });
+    }
+
+    let mut layers = Vec::new();
+    tmx_layers(&doc, map, &mut layers)?;
+
+    Ok(TiledMap {
+        width: attribute(&doc, map, "width")?,
+        height: attribute(&doc, map, "height")?,
+        tile_width: attribute(&doc, map, "tilewidth")?,
+        tile_height: attribute(&doc, map, "tileheight")?,
+        properties: tmx_properties(map),
+        property_positions: tmx_property_nodes(map)
+            .map(|(name, node)| (name.to_string(), xml_position(&doc, node)))
+            .collect(),
+        tilesets,
+        layers,
+    })
+}
+
+fn xml_position(doc: &Document, node: Node) -> Position {
+    let position = doc.text_pos_at(node.range().start);
+    Position::Text {
+        line: position.row as usize,
+        column: position.col as usize,
+    }
+}
+
+fn xml_error(doc: &Document, node: Node, message: String) -> Diagnostic {
+    error(xml_position(doc, node), message)
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn attribute<T: FromStr>(
+    doc: &Document,
+    node: Node,
+    name: &str,
+) -> Result<T, Diagnostic> {
+    node.attribute(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| {
+            xml_error(
+                doc,
+                node,
+                format!(
+                    "<{}> needs a valid '{}' attribute",
+                    node.tag_name().name(),
+                    name
+                ),
+            )
+        })
+}
+
+/// The `<property>` nodes of `node` by name.
+fn tmx_property_nodes<'a, 'input>(
+    node: Node<'a, 'input>,
+) -> impl Iterator<Item = (&'a str, Node<'a, 'input>)> {
+    children(node, "properties")
+        .flat_map(|properties| children(properties, "property"))
+        .filter_map(|property| Some((property.attribute("name")?, property)))
+}
+
+fn tmx_properties(node: Node) -> Properties {
+    tmx_property_nodes(node)
+        .map(|(name, property)| {
+            // Multiline strings are stored as text instead of an attribute
+            let value = property
+                .attribute("value")
+                .or_else(|| property.text())
+                .unwrap_or_default();
+            (name.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Flattens group layers, keeping the drawing order.
+fn tmx_layers(
+    doc: &Document,
+    parent: Node,
+    layers: &mut Vec<Layer>,
+) -> Result<(), Diagnostic> {
+    for node in parent.children() {
+        match node.tag_name().name() {
+            "layer" => {
+                let data = children(node, "data").next().ok_or_else(|| {
+                    xml_error(doc, node, "layer has no <data>".to_string())
+                })?;
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => data
+                        .text()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|gid| gid.trim().parse())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|_| {
+                            xml_error(
+                                doc,
+                                data,
+                                "invalid tile data".to_string(),
+                            )
+                        })?,
+                    None => children(data, "tile")
+                        .map(|tile| tile.attribute("gid").unwrap_or("0"))
+                        .map(|gid| gid.parse().unwrap_or(0))
+                        .collect(),
+                    Some(_) => {
+                        return Err(encoded_layer(xml_position(doc, data)));
+                    }
+                };
+                layers.push(Layer::Tiles(gids, xml_position(doc, node)));
+            }
+            "objectgroup" => {
+                let mut objects = Vec::new();
+                for object in children(node, "object") {
+                    objects.push(Object {
+                        name: object
+                            .attribute("name")
+                            .unwrap_or_default()
+                            .to_string(),
+                        kind: object
+                            .attribute("type")
+                            .or_else(|| object.attribute("class"))
+                            .unwrap_or_default()
+                            .to_string(),
+                        x: attribute(doc, object, "x")?,
+                        y: attribute(doc, object, "y")?,
+                        width: attribute(doc, object, "width").unwrap_or(0.0),
+                        height: attribute(doc, object, "height").unwrap_or(0.0),
+                        tile: object.has_attribute("gid"),
+                        properties: tmx_properties(object),
+                    });
+                }
+                layers.push(Layer::Objects(objects));
+            }
+            "group" => tmx_layers(doc, node, layers)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Loads Tiled maps (`*.tmx` and `*.tmj`) as [`MapData`] assets.
+#[derive(Default)]
+pub struct TiledLoader;
+
+impl AssetLoader for TiledLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let path = load_context.path();
+            let format = TiledFormat::from_path(path)
+                .ok_or_else(|| anyhow::anyhow!("not a Tiled map"))?;
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let (map, diagnostics) =
+                check_tiled_map(std::str::from_utf8(bytes)?, format, &name);
+            log_diagnostics(path, &diagnostics);
+
+            let map = map.ok_or(MapError { diagnostics })?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx", "tmj"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map width="2" height="2" tilewidth="8" tileheight="8" infinite="0">
+ <properties>
+  <property name="fog_of_war" type="int" value="4"/>
+ </properties>
+ <tileset firstgid="1" name="ascii" tilewidth="8" tileheight="8">
+  <tile id="35">
+   <properties>
+    <property name="collider" type="bool" value="true"/>
+   </properties>
+  </tile>
+  <tile id="46">
+   <properties>
+    <property name="speed" type="float" value="0.5"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="ground" width="2" height="2">
+  <data encoding="csv">
+36,47,
+47,36
+</data>
+ </layer>
+ <objectgroup id="2" name="objects">
+  <object id="1" type="player_start" x="8" y="0" width="8" height="8"/>
+ </objectgroup>
+</map>
+"#;
+
+    const TMJ: &str = r#"{
+ "width": 2, "height": 2, "tilewidth": 8, "tileheight": 8, "infinite": false,
+ "properties": [{"name": "fog_of_war", "type": "int", "value": 4}],
+ "tilesets": [{"firstgid": 1, "name": "ascii", "tiles": [
+  {"id": 35, "properties": [
+   {"name": "collider", "type": "bool", "value": true}
+  ]},
+  {"id": 46, "properties": [
+   {"name": "speed", "type": "float", "value": 0.5}
+  ]}
+ ]}],
+ "layers": [
+  {"type": "tilelayer", "name": "ground", "data": [36, 47, 47, 36]},
+  {"type": "objectgroup", "name": "objects", "objects": [
+   {"id": 1, "type": "player_start", "x": 8, "y": 0, "width": 8, "height": 8}
+  ]}
+ ]
+}
+"#;
+
+    fn tmx(text: &str) -> Result<MapData, Diagnostic> {
+        parse_tiled(text, TiledFormat::Tmx, "shrine")
+    }
+
+    fn tmj(text: &str) -> Result<MapData, Diagnostic> {
+        parse_tiled(text, TiledFormat::Tmj, "shrine")
+    }
+
+    fn assert_error(
+        result: Result<MapData, Diagnostic>,
+        position: Position,
+        message: &str,
+    ) {
+        let diagnostic = result.err().expect("expected an error");
+        assert_eq!(diagnostic.position, position);
+        assert!(
+            diagnostic.message.contains(message),
+            "{}",
+            diagnostic.message
+        );
+    }
+
+    #[test]
+    fn both_formats_convert_the_same() {
+        let (from_tmx, from_tmj) = (tmx(TMX).unwrap(), tmj(TMJ).unwrap());
+        assert_eq!(from_tmx.rows, from_tmj.rows);
+        assert_eq!(from_tmx.header.legend, from_tmj.header.legend);
+        assert_eq!(from_tmx.header.fog_of_war, Some(4));
+
+        let wall = &from_tmx.header.legend[&from_tmx.rows[0][0]];
+        assert_eq!(wall.index, Some('#' as usize));
+        assert!(wall.collider);
+        let start = &from_tmx.header.legend[&from_tmx.rows[0][1]];
+        assert!(start.player_start);
+        assert_eq!(start.terrain.speed, 0.5);
+        assert_eq!(from_tmx.rows[0][0], from_tmx.rows[1][1]);
+    }
+
+    #[test]
+    fn rejects_external_tilesets() {
+        let text = TMX.replace(
+            r#"<tileset firstgid="1""#,
+            r#"<tileset firstgid="1" source="ascii.tsx""#,
+        );
+        let line = Position::Text { line: 6, column: 2 };
+        assert_error(tmx(&text), line, "external tileset 'ascii.tsx'");
+
+        let text = TMJ.replace(
+            r#""firstgid": 1,"#,
+            r#""firstgid": 1, "source": "ascii.tsj","#,
+        );
+        let message = "external tileset 'ascii.tsj'";
+        assert_error(tmj(&text), Position::File, message);
+    }
+
+    #[test]
+    fn rejects_encoded_layers() {
+        let text = TMX.replace(
+            r#"encoding="csv""#,
+            r#"encoding="base64" compression="zlib""#,
+        );
+        let line = Position::Text { line: 19, column: 3 };
+        assert_error(tmx(&text), line, "only CSV");
+
+        let text = TMJ.replace(
+            r#""data": [36, 47, 47, 36]"#,
+            r#""data": "eJxjYGBgAAAABAAB", "compression": "zlib""#,
+        );
+        assert_error(tmj(&text), Position::File, "only CSV");
+    }
+
+    #[test]
+    fn rejects_tiles_in_no_tileset() {
+        let text = TMX
+            .replace(r#"firstgid="1""#, r#"firstgid="10""#)
+            .replace("\n47,36\n", "\n47,3\n");
+        let tile = Position::Tile { x: 1, y: 1 };
+        assert_error(tmx(&text), tile, "tile 3 is in no tileset");
+    }
+
+    #[test]
+    fn rejects_properties_of_the_wrong_type() {
+        let text = TMX.replace(r#"value="0.5""#, r#"value="fast""#);
+        let tile = Position::Tile { x: 1, y: 0 };
+        assert_error(tmx(&text), tile, "'fast' is not a number");
+
+        let text = TMX.replace(r#"value="4""#, r#"value="far""#);
+        let line = Position::Text { line: 4, column: 3 };
+        assert_error(tmx(&text), line, "fog_of_war 'far' is not a number");
+
+        let text = TMJ.replace(r#""value": 4"#, r#""value": "far""#);
+        assert_error(tmj(&text), Position::File, "fog_of_war 'far'");
+    }
+}
@@ -1,37 +1,249 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    collections::HashSet,
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    ascii::{spawn_ascii_sprite, AsciiSheet},
-    GameState, TILE_SIZE, npc::Npc,
+    chunk::CHUNK_SIZE,
+    clock::TimeOfDay,
+    generator::{generate, GeneratorConfig},
+    interact::{restored_tile, Interactable, MapChanges, MapState, Toggle},
+    map::{
+        log_diagnostics, MapData, MapEntity, MapLoader, MapRegistry, Terrain,
+        TileAnimation, TileDef,
+    },
+    npc::Npc,
+    player::Player,
+    tiled::TiledLoader,
+    validation::check_map_data,
+    weather::Weather,
+    GameState, TILE_SIZE,
 };
-#[derive(Component)]
-pub struct TileCollider;
+
+/// Seeds tried for a generated map without a fixed seed.
+const GENERATE_ATTEMPTS: usize = 8;
+
+/// Source of [`TileGrid::revision`], shared by every grid so a new map never
+/// reuses the revision of the previous one.
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
 
 #[derive(Component)]
 pub struct Map;
 
-#[derive(Component)]
-pub struct EncounterSpawner;
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Warp {
+    pub map: String,
+    pub spawn: String,
+}
+
+/// Sent once the screen is dark to swap the current map for the warp target.
+pub struct WarpEvent(pub Warp);
+
+/// The map the player is on, spawned as soon as its asset has loaded. Starts
+/// out as the start map from the registry.
+pub struct CurrentMap {
+    pub id: String,
+    /// Seed of a generated map, it is a different map with every seed.
+    pub seed: Option<u64>,
+    pub handle: Handle<MapData>,
+    /// Where to place the player once the map is spawned.
+    pub spawn_point: Option<String>,
+    pub spawned: bool,
+}
+
+/// Tells maps apart across visits, unlike asset handles which change for
+/// every generated map.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MapKey {
+    pub id: String,
+    pub seed: Option<u64>,
+}
+
+/// Gameplay data of a single map tile, see [`TileGrid`].
+#[derive(Clone)]
+pub struct GridTile {
+    /// Atlas index drawn, before any animation.
+    pub index: usize,
+    pub color: Color,
+    pub animation: Option<TileAnimation>,
+    /// Scales the color, e.g. for tiles out of sight.
+    pub brightness: f32,
+    /// How much of its color the tile keeps at night, from light sources
+    /// nearby.
+    pub light: f32,
+    pub collider: bool,
+    /// Encounter zone id, fights are rolled from its encounter table.
+    pub encounter: Option<String>,
+    pub warp: Option<Warp>,
+    pub terrain: Terrain,
+    pub weather: Option<Weather>,
+    pub npc: Option<Npc>,
+    /// Time of day the NPC is around, always when `None`.
+    pub available: Option<TimeOfDay>,
+    pub interact: Option<Interactable>,
+    pub toggle: Option<Toggle>,
+}
+
+/// Tiles of the current map indexed by tile coordinates. Maps are drawn by
+/// chunk, so this is all there is of a tile, and systems only look at the
+/// few tiles around a position.
+#[derive(Default)]
+pub struct TileGrid {
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<Option<GridTile>>,
+    /// Chunks with tiles changed since they were last drawn.
+    dirty_chunks: HashSet<(usize, usize)>,
+    /// Changes with every collider or terrain change, but not with visual
+    /// ones like lighting, so paths are only found again when they can
+    /// differ.
+    revision: u64,
+}
+
+impl CurrentMap {
+    pub fn key(&self) -> MapKey {
+        MapKey {
+            id: self.id.clone(),
+            seed: self.seed,
+        }
+    }
+}
+
+impl TileGrid {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        TileGrid {
+            width,
+            height,
+            tiles: vec![None; width * height],
+            dirty_chunks: HashSet::new(),
+            revision: NEXT_REVISION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&GridTile> {
+        if x < 0
+            || y < 0
+            || x as usize >= self.width
+            || y as usize >= self.height
+        {
+            return None;
+        }
+        self.tiles[y as usize * self.width + x as usize].as_ref()
+    }
+
+    /// The tile at `(x, y)` for changing how it looks, which redraws its
+    /// chunk. Colliders and terrain are changed through
+    /// [`Self::collision_mut`].
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut GridTile> {
+        if x < 0
+            || y < 0
+            || x as usize >= self.width
+            || y as usize >= self.height
+        {
+            return None;
+        }
+        self.dirty_chunks
+            .insert((x as usize / CHUNK_SIZE, y as usize / CHUNK_SIZE));
+        self.tiles[y as usize * self.width + x as usize].as_mut()
+    }
+
+    /// The tile at `(x, y)` for changing its collider or terrain, which
+    /// also invalidates paths found over the grid.
+    pub fn collision_mut(&mut self, x: i32, y: i32) -> Option<&mut GridTile> {
+        self.get(x, y)?;
+        self.revision = NEXT_REVISION.fetch_add(1, Ordering::Relaxed);
+        self.get_mut(x, y)
+    }
+
+    /// Every tile with its tile coordinates.
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &GridTile)> + '_ {
+        self.tiles.iter().enumerate().filter_map(move |(i, tile)| {
+            let position = ((i % self.width) as i32, (i / self.width) as i32);
+            tile.as_ref().map(|tile| (position, tile))
+        })
+    }
+
+    pub fn has_dirty_chunks(&self) -> bool {
+        !self.dirty_chunks.is_empty()
+    }
+
+    pub fn take_dirty_chunks(&mut self) -> HashSet<(usize, usize)> {
+        std::mem::take(&mut self.dirty_chunks)
+    }
+
+    pub(crate) fn set(&mut self, x: usize, y: usize, tile: GridTile) {
+        self.tiles[y * self.width + x] = Some(tile);
+    }
+
+    /// Tile coordinates of the tile whose center is closest to `translation`.
+    pub fn tile_coords(translation: Vec3) -> (i32, i32) {
+        (
+            (translation.x / TILE_SIZE).round() as i32,
+            (-translation.y / TILE_SIZE).round() as i32,
+        )
+    }
+
+    pub fn tile_at(&self, translation: Vec3) -> Option<&GridTile> {
+        let (x, y) = Self::tile_coords(translation);
+        self.get(x, y)
+    }
+
+    /// The tile under `translation` and its eight neighbours, with the
+    /// world position of each tile center.
+    pub fn neighborhood(
+        &self,
+        translation: Vec3,
+    ) -> impl Iterator<Item = (Vec3, &GridTile)> + '_ {
+        let (x, y) = Self::tile_coords(translation);
+        (y - 1..=y + 1)
+            .flat_map(move |ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+            .filter_map(move |(nx, ny)| {
+                self.get(nx, ny).map(|tile| {
+                    let center = Vec3::new(
+                        nx as f32 * TILE_SIZE,
+                        -(ny as f32) * TILE_SIZE,
+                        translation.z,
+                    );
+                    (center, tile)
+                })
+            })
+    }
+}
 
 pub struct TileMapPlugin;
 
 impl Plugin for TileMapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(
-            SystemSet::on_resume(GameState::Overworld).with_system(show_map),
-        )
-        .add_system_set(
-            SystemSet::on_pause(GameState::Overworld).with_system(hide_map),
-        )
-        .add_system_set(
-            SystemSet::on_enter(GameState::Overworld)
-                .with_system(create_simple_map),
-        );
+        app.add_asset::<MapData>()
+            .init_asset_loader::<MapLoader>()
+            .init_asset_loader::<TiledLoader>()
+            .init_resource::<TileGrid>()
+            .add_event::<WarpEvent>()
+            .add_startup_system(load_map_registry)
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(change_map)
+                    .with_system(reload_map)
+                    .with_system(
+                        spawn_current_map.after(change_map).after(reload_map),
+                    ),
+            )
+            .add_system_set(
+                SystemSet::on_resume(GameState::Overworld)
+                    .with_system(show_map),
+            )
+            .add_system_set(
+                SystemSet::on_pause(GameState::Overworld).with_system(hide_map),
+            );
     }
 }
 
@@ -61,52 +273,217 @@ fn show_map(
     }
 }
 
-fn create_simple_map(mut commands: Commands, ascii: Res<AsciiSheet>) {
-    let file = File::open("assets/map.txt").expect("No map file found!");
-
-    let mut tiles = Vec::new();
-
-    for (y, line) in BufReader::new(file).lines().enumerate() {
-        if let Ok(line) = line {
-            for (x, char) in line.chars().enumerate() {
-                let color = match char {
-                    '#' => Color::rgb(0.7,0.7,0.7),
-                    '@' => Color::rgb(0.5,0.5,0.2),
-                    '~' => Color::rgb(0.2,0.9,0.2),
-                    _ => Color::rgb(0.9,0.9,0.9),
-                };
-                let tile = spawn_ascii_sprite(
-                    &mut commands,
-                    &ascii,
-                    char as usize,
-                    color,
-                    // Color::rgb(0.9, 0.9, 0.9),
-                    Vec3::new(
-                        x as f32 * TILE_SIZE,
-                        -(y as f32) * TILE_SIZE,
-                        100.0,
-                    ),
-                    Vec3::splat(1.0),
-                );
-                if char == '#' {
-                    commands.entity(tile).insert(TileCollider);
-                }
-                if char == '~' {
-                    commands.entity(tile).insert(EncounterSpawner);
-                }
-                if char == '@' {
-                    commands.entity(tile).insert(Npc::Healer).insert(TileCollider);
+pub fn tile_translation(x: usize, y: usize) -> Vec2 {
+    Vec2::new(x as f32 * TILE_SIZE, -(y as f32) * TILE_SIZE)
+}
+
+fn load_map_registry(mut commands: Commands, assets: Res<AssetServer>) {
+    let text =
+        fs::read_to_string("assets/maps.ron").expect("No map registry found!");
+    let mut registry: MapRegistry =
+        ron::from_str(&text).expect("Invalid map registry!");
+    registry.handles = registry
+        .maps
+        .iter()
+        .map(|(id, path)| (id.clone(), assets.load(path.as_str())))
+        .collect();
+
+    commands.insert_resource(CurrentMap {
+        id: registry.start.clone(),
+        seed: None,
+        handle: map_handle(&registry, &registry.start),
+        spawn_point: None,
+        spawned: false,
+    });
+    commands.insert_resource(registry);
+}
+
+fn map_handle(registry: &MapRegistry, id: &str) -> Handle<MapData> {
+    registry
+        .handles
+        .get(id)
+        .unwrap_or_else(|| panic!("Map {} is not registered!", id))
+        .clone()
+}
+
+fn change_map(
+    mut commands: Commands,
+    mut warp_events: EventReader<WarpEvent>,
+    map_query: Query<Entity, With<Map>>,
+    registry: Res<MapRegistry>,
+    mut maps: ResMut<Assets<MapData>>,
+) {
+    if let Some(WarpEvent(warp)) = warp_events.iter().last() {
+        for map in map_query.iter() {
+            commands.entity(map).despawn_recursive();
+        }
+
+        let (seed, handle) = match registry.generated.get(&warp.map) {
+            Some(config) => {
+                let (seed, map) = generate_map(&warp.map, config);
+                (Some(seed), maps.add(map))
+            }
+            None => (None, map_handle(&registry, &warp.map)),
+        };
+
+        commands.insert_resource(CurrentMap {
+            id: warp.map.clone(),
+            seed,
+            handle,
+            spawn_point: Some(warp.spawn.clone()),
+            spawned: false,
+        });
+    }
+}
+
+/// Generates the map `id` and validates it like a map file. Random seeds are
+/// rolled again until a map passes, a fixed seed is used even with errors.
+fn generate_map(id: &str, config: &GeneratorConfig) -> (u64, MapData) {
+    let mut attempts = 0;
+    loop {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        let (map, diagnostics) = check_map_data(generate(config, seed));
+        let path = PathBuf::from(format!("{} (seed {})", id, seed));
+        log_diagnostics(&path, &diagnostics);
+
+        attempts += 1;
+        match map {
+            Some(map) => return (seed, map),
+            None if config.seed.is_some() || attempts == GENERATE_ATTEMPTS => {
+                error!("Using generated map {} with errors", id);
+                return (seed, generate(config, seed));
+            }
+            None => {}
+        }
+    }
+}
+
+/// Respawns the current map when its file is edited while the game runs.
+fn reload_map(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<MapData>>,
+    map_query: Query<Entity, With<Map>>,
+    current: Option<ResMut<CurrentMap>>,
+) {
+    let mut current = match current {
+        Some(current) => current,
+        None => return,
+    };
+
+    for event in asset_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if *handle == current.handle && current.spawned {
+                for map in map_query.iter() {
+                    commands.entity(map).despawn_recursive();
                 }
-                tiles.push(tile);
+                current.spawned = false;
             }
         }
     }
+}
+
+fn spawn_current_map(
+    mut commands: Commands,
+    current: Option<ResMut<CurrentMap>>,
+    maps: Res<Assets<MapData>>,
+    mut player_query: Query<(&mut Player, &mut Transform)>,
+    changes: Res<MapChanges>,
+) {
+    let mut current = match current {
+        Some(current) if !current.spawned => current,
+        _ => return,
+    };
+    let map = match maps.get(&current.handle) {
+        Some(map) => map,
+        None => return,
+    };
+
+    spawn_map(&mut commands, map, changes.0.get(&current.key()));
+    current.spawned = true;
+
+    if let Some(spawn_point) = current.spawn_point.take() {
+        let tile = map.spawn_point(&spawn_point).or_else(|| {
+            error!("Map {} has no spawn point {}", current.id, spawn_point);
+            map.arrival()
+        });
+        let (mut player, mut transform) = player_query.single_mut();
+        if let Some((x, y)) = tile {
+            let target = tile_translation(x, y);
+            transform.translation.x = target.x;
+            transform.translation.y = target.y;
+        }
+        player.active = true;
+    }
+}
+
+/// Fills the grid and spawns the map, its chunks are spawned around the
+/// camera by the chunk plugin.
+fn spawn_map(
+    commands: &mut Commands,
+    map: &MapData,
+    state: Option<&MapState>,
+) -> Entity {
+    let unknown = TileDef::default();
+    let width = map.rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut grid = TileGrid::new(width, map.rows.len());
+    let mut lights = Vec::new();
+
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            let def = map.tile_def(*glyph).unwrap_or(&unknown);
+            let (index, collider, toggle) =
+                restored_tile(def, def.atlas_index(*glyph), (x, y), state);
+            let npc = def.spawn.map(|MapEntity::Npc(npc)| npc);
+            if let Some(radius) = def.light {
+                lights.push(((x as i32, y as i32), radius));
+            }
+            grid.set(
+                x,
+                y,
+                GridTile {
+                    index,
+                    color: def.color(),
+                    animation: def.animation.clone(),
+                    brightness: 1.0,
+                    light: if map.header.indoors { 1.0 } else { 0.0 },
+                    collider,
+                    encounter: def.encounter.clone(),
+                    warp: def.warp.clone(),
+                    terrain: def.terrain.clone(),
+                    weather: def.weather,
+                    npc,
+                    available: def.available,
+                    interact: def.interact.clone(),
+                    toggle,
+                },
+            );
+        }
+    }
+    for (position, radius) in lights {
+        light_tiles(&mut grid, position, radius);
+    }
+    commands.insert_resource(grid);
 
     commands
         .spawn()
         .insert(Map)
-        .insert(Name::new("Map"))
+        .insert(Name::new(map.header.name.clone()))
         .insert(Transform::default())
         .insert(GlobalTransform::default())
-        .push_children(&tiles);
+        .id()
+}
+
+/// Lights the tiles within `radius` of a light source, fading out towards
+/// the edge.
+fn light_tiles(grid: &mut TileGrid, (x, y): (i32, i32), radius: f32) {
+    let reach = radius.ceil() as i32;
+    for ny in y - reach..=y + reach {
+        for nx in x - reach..=x + reach {
+            let distance = Vec2::new((nx - x) as f32, (ny - y) as f32).length();
+            let light = 1.0 - distance / (radius + 1.0);
+            if let Some(tile) = grid.get_mut(nx, ny) {
+                tile.light = tile.light.max(light);
+            }
+        }
+    }
 }
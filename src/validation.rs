@@ -0,0 +1,610 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt,
+};
+
+use crate::{
+    generator::ENTRANCE_SPAWN_POINT,
+    interact::Interactable,
+    map::{MapData, MapRegistry},
+    tilemap::Warp,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Where a problem is: a 1-based line and column of the file, or the tile
+/// coordinates on maps that aren't text, like Tiled and generated maps.
+/// `File` is for problems with nothing more precise to point at, like the
+/// values of a JSON map, which keep no position once parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Position {
+    Text { line: usize, column: usize },
+    Tile { x: usize, y: usize },
+    File,
+}
+
+/// A problem found in a map file.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub position: Position,
+    pub message: String,
+}
+
+/// Returned by the map loader when a map file has errors.
+#[derive(Debug)]
+pub struct MapError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::Text { line, column } => write!(f, "{}:{}", line, column),
+            Position::Tile { x, y } => write!(f, "tile ({}, {})", x, y),
+            Position::File => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            // Printed after the path, like `map.tmj: error: ...`
+            Position::File => write!(f, " {}: {}", self.severity, self.message),
+            position => {
+                write!(f, "{}: {}: {}", position, self.severity, self.message)
+            }
+        }
+    }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let errors = self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
+        write!(f, "map has {} error(s)", errors)
+    }
+}
+
+impl Error for MapError {}
+
+/// Parses and validates a map file. The map is only returned when no errors
+/// were found, warnings are reported either way.
+pub fn check_map(text: &str) -> (Option<MapData>, Vec<Diagnostic>) {
+    match MapData::parse(text) {
+        Ok(map) => check_map_data(map),
+        Err(diagnostic) => (None, vec![diagnostic]),
+    }
+}
+
+/// Validates an already parsed map, e.g. one converted from another format.
+pub fn check_map_data(map: MapData) -> (Option<MapData>, Vec<Diagnostic>) {
+    let mut diagnostics = Vec::new();
+    check_glyphs(&map, &mut diagnostics);
+    check_tile_defs(&map, &mut diagnostics);
+    check_switches(&map, &mut diagnostics);
+    check_spawn_points(&map, &mut diagnostics);
+    check_borders(&map, &mut diagnostics);
+    check_reachability(&map, &mut diagnostics);
+
+    let map = if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        None
+    } else {
+        Some(map)
+    };
+
+    (map, diagnostics)
+}
+
+/// Checks across maps that every warp of `map` leads to a registered map
+/// and to a spawn point on it. `maps` holds the registered maps by id, warps
+/// to maps missing from it only have to be registered.
+pub fn check_warps(
+    map: &MapData,
+    registry: &MapRegistry,
+    maps: &HashMap<String, MapData>,
+) -> Vec<Diagnostic> {
+    let mut glyphs: Vec<&char> = map.header.legend.keys().collect();
+    glyphs.sort();
+
+    let mut diagnostics = Vec::new();
+    for glyph in glyphs {
+        let warp = match map.header.legend[glyph].warp.as_ref() {
+            Some(warp) => warp,
+            None => continue,
+        };
+        let tile = match map.find_tile(|def| def.warp.as_ref() == Some(warp)) {
+            Some(tile) => tile,
+            None => continue,
+        };
+        if let Some(message) = warp_error(warp, registry, maps) {
+            diagnostics.push(diagnostic(map, Severity::Error, tile, message));
+        }
+    }
+    diagnostics
+}
+
+/// Why `warp` leads nowhere, `None` when it's fine.
+pub fn warp_error(
+    warp: &Warp,
+    registry: &MapRegistry,
+    maps: &HashMap<String, MapData>,
+) -> Option<String> {
+    let has_spawn_point = if registry.generated.contains_key(&warp.map) {
+        warp.spawn == ENTRANCE_SPAWN_POINT
+    } else if registry.maps.contains_key(&warp.map) {
+        maps.get(&warp.map)
+            .map_or(true, |target| target.spawn_point(&warp.spawn).is_some())
+    } else {
+        return Some(format!("warp leads to unregistered map '{}'", warp.map));
+    };
+
+    if has_spawn_point {
+        None
+    } else {
+        Some(format!(
+            "warp leads to spawn point '{}' missing on map '{}'",
+            warp.spawn, warp.map
+        ))
+    }
+}
+
+fn diagnostic(
+    map: &MapData,
+    severity: Severity,
+    (x, y): (usize, usize),
+    message: String,
+) -> Diagnostic {
+    let position = match map.layout_line {
+        Some(line) => Position::Text {
+            line: line + y,
+            column: x + 1,
+        },
+        None => Position::Tile { x, y },
+    };
+    Diagnostic {
+        severity,
+        position,
+        message,
+    }
+}
+
+fn width(map: &MapData) -> usize {
+    map.rows.iter().map(|row| row.len()).max().unwrap_or(0)
+}
+
+fn glyph_at(map: &MapData, x: isize, y: isize) -> Option<char> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    map.rows.get(y as usize)?.get(x as usize).copied()
+}
+
+/// Locked doors and switched tiles count as walkable, they can be opened.
+fn is_walkable(map: &MapData, glyph: char) -> bool {
+    map.tile_def(glyph).map_or(true, |def| {
+        !def.collider
+            || def.toggle.is_some()
+            || matches!(def.interact, Some(Interactable::Door { .. }))
+    })
+}
+
+fn check_glyphs(map: &MapData, diagnostics: &mut Vec<Diagnostic>) {
+    let width = width(map);
+
+    if map.rows.is_empty() {
+        diagnostics.push(diagnostic(
+            map,
+            Severity::Error,
+            (0, 0),
+            "map layout is empty".to_string(),
+        ));
+    }
+
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            if map.tile_def(*glyph).is_none() {
+                diagnostics.push(diagnostic(
+                    map,
+                    Severity::Error,
+                    (x, y),
+                    format!("unknown glyph '{}' is not in the legend", glyph),
+                ));
+            }
+        }
+        if row.len() < width {
+            diagnostics.push(diagnostic(
+                map,
+                Severity::Warning,
+                (row.len(), y),
+                format!("row is {} tiles wide, expected {}", row.len(), width),
+            ));
+        }
+    }
+}
+
+/// Checks every legend entry once, at the first tile using it.
+fn check_tile_defs(map: &MapData, diagnostics: &mut Vec<Diagnostic>) {
+    let mut checked = Vec::new();
+
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            let def = match map.tile_def(*glyph) {
+                Some(def) if !checked.contains(glyph) => def,
+                _ => continue,
+            };
+            checked.push(*glyph);
+
+            if def.terrain.speed <= 0.0 && !def.collider {
+                diagnostics.push(diagnostic(
+                    map,
+                    Severity::Warning,
+                    (x, y),
+                    format!(
+                        "terrain speed of '{}' is not positive, the player \
+                         gets stuck on it",
+                        glyph
+                    ),
+                ));
+            }
+            if matches!(def.interact, Some(Interactable::Door { .. }))
+                && !def.collider
+            {
+                diagnostics.push(diagnostic(
+                    map,
+                    Severity::Warning,
+                    (x, y),
+                    format!(
+                        "door '{}' is not a collider, it never blocks the way",
+                        glyph
+                    ),
+                ));
+            }
+            if let Some(animation) = &def.animation {
+                if animation.frame_time.is_nan() || animation.frame_time <= 0.0
+                {
+                    diagnostics.push(diagnostic(
+                        map,
+                        Severity::Error,
+                        (x, y),
+                        format!(
+                            "animation of '{}' needs a positive frame_time",
+                            glyph
+                        ),
+                    ));
+                }
+                if animation.frames.is_empty() && animation.colors.is_empty() {
+                    diagnostics.push(diagnostic(
+                        map,
+                        Severity::Warning,
+                        (x, y),
+                        format!(
+                            "animation of '{}' has no frames or colors",
+                            glyph
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Switches should toggle at least one tile and toggled tiles need a switch.
+fn check_switches(map: &MapData, diagnostics: &mut Vec<Diagnostic>) {
+    let mut switches = Vec::new();
+    let mut toggles = Vec::new();
+
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            let def = match map.tile_def(*glyph) {
+                Some(def) => def,
+                None => continue,
+            };
+            if let Some(Interactable::Switch(switch)) = &def.interact {
+                switches.push((switch.as_str(), (x, y)));
+            }
+            if let Some(toggle) = &def.toggle {
+                toggles.push((toggle.switch.as_str(), (x, y)));
+            }
+        }
+    }
+
+    for (switch, position) in switches.iter() {
+        if !toggles.iter().any(|(toggled, _)| toggled == switch) {
+            diagnostics.push(diagnostic(
+                map,
+                Severity::Warning,
+                *position,
+                format!("switch '{}' does not toggle any tile", switch),
+            ));
+        }
+    }
+    for (switch, position) in toggles.iter() {
+        if !switches.iter().any(|(name, _)| name == switch) {
+            diagnostics.push(diagnostic(
+                map,
+                Severity::Warning,
+                *position,
+                format!("tile is toggled by missing switch '{}'", switch),
+            ));
+        }
+    }
+}
+
+fn check_spawn_points(map: &MapData, diagnostics: &mut Vec<Diagnostic>) {
+    let mut seen = Vec::new();
+    let mut player_start = false;
+
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            let def = match map.tile_def(*glyph) {
+                Some(def) => def,
+                None => continue,
+            };
+            if def.player_start {
+                if player_start {
+                    diagnostics.push(diagnostic(
+                        map,
+                        Severity::Warning,
+                        (x, y),
+                        "player start is placed more than once, only the \
+                         first one is used"
+                            .to_string(),
+                    ));
+                }
+                player_start = true;
+            }
+
+            let name = match def.spawn_point.as_ref() {
+                Some(name) => name,
+                None => continue,
+            };
+            if seen.contains(&name) {
+                diagnostics.push(diagnostic(
+                    map,
+                    Severity::Warning,
+                    (x, y),
+                    format!(
+                        "spawn point '{}' is placed more than once, \
+                         only the first one is used",
+                        name
+                    ),
+                ));
+            } else {
+                seen.push(name);
+            }
+        }
+    }
+
+    if seen.is_empty() && !player_start {
+        diagnostics.push(diagnostic(
+            map,
+            Severity::Error,
+            (0, 0),
+            "map has no player start or spawn point".to_string(),
+        ));
+    }
+}
+
+/// Walkable tiles next to the edge of the layout would let the player walk
+/// off the map, unless they are warps leading somewhere else.
+fn check_borders(map: &MapData, diagnostics: &mut Vec<Diagnostic>) {
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            let leads_away =
+                map.tile_def(*glyph).map_or(false, |def| def.warp.is_some());
+            if !is_walkable(map, *glyph) || leads_away {
+                continue;
+            }
+
+            let (x, y) = (x as isize, y as isize);
+            let open = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .iter()
+                .any(|&(nx, ny)| glyph_at(map, nx, ny).is_none());
+            if open {
+                diagnostics.push(diagnostic(
+                    map,
+                    Severity::Error,
+                    (x as usize, y as usize),
+                    "walkable tile on the map edge, the border is not closed"
+                        .to_string(),
+                ));
+            }
+        }
+    }
+}
+
+/// Flood fills from every spawn point and the player start and reports each
+/// walkable area the player can never get to once, at its first tile.
+fn check_reachability(map: &MapData, diagnostics: &mut Vec<Diagnostic>) {
+    let width = width(map);
+    let mut reached = vec![false; width * map.rows.len()];
+
+    let spawn_points = map.rows.iter().enumerate().flat_map(|(y, row)| {
+        row.iter().enumerate().filter_map(move |(x, glyph)| {
+            map.tile_def(*glyph)
+                .filter(|def| def.spawn_point.is_some() || def.player_start)
+                .map(|_| (x, y))
+        })
+    });
+    let spawn_points: Vec<(usize, usize)> = spawn_points.collect();
+    if spawn_points.is_empty() {
+        return;
+    }
+    flood_fill(map, width, &spawn_points, &mut reached);
+
+    for (y, row) in map.rows.iter().enumerate() {
+        for (x, glyph) in row.iter().enumerate() {
+            if reached[y * width + x] || !is_walkable(map, *glyph) {
+                continue;
+            }
+            let area = flood_fill(map, width, &[(x, y)], &mut reached);
+            diagnostics.push(diagnostic(
+                map,
+                Severity::Warning,
+                (x, y),
+                format!(
+                    "area of {} walkable tile(s) is unreachable from any \
+                     spawn point",
+                    area
+                ),
+            ));
+        }
+    }
+}
+
+fn flood_fill(
+    map: &MapData,
+    width: usize,
+    start: &[(usize, usize)],
+    reached: &mut [bool],
+) -> usize {
+    let mut queue: VecDeque<(usize, usize)> = start.iter().copied().collect();
+    let mut count = 0;
+    for &(x, y) in start {
+        reached[y * width + x] = true;
+    }
+
+    while let Some((x, y)) = queue.pop_front() {
+        count += 1;
+        let (x, y) = (x as isize, y as isize);
+        for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+            let glyph = match glyph_at(map, nx, ny) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let index = ny as usize * width + nx as usize;
+            if !reached[index] && is_walkable(map, glyph) {
+                reached[index] = true;
+                queue.push_back((nx as usize, ny as usize));
+            }
+        }
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map file with walls, floor and a player start, the layout starting
+    /// on line 10.
+    fn map_file(layout: &[&str]) -> String {
+        let header = r#"(
+    name: "Test",
+    legend: {
+        '#': (collider: true),
+        '.': (),
+        'S': (player_start: true),
+    },
+)
+---
+"#;
+        header.to_string() + &layout.join("\n")
+    }
+
+    fn single(diagnostics: &[Diagnostic]) -> &Diagnostic {
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        &diagnostics[0]
+    }
+
+    #[test]
+    fn valid_map_has_no_diagnostics() {
+        let (map, diagnostics) =
+            check_map(&map_file(&["####", "#S.#", "####"]));
+        assert!(map.is_some());
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn unknown_glyph_is_an_error() {
+        let (map, diagnostics) =
+            check_map(&map_file(&["#####", "#S.X#", "#####"]));
+        assert!(map.is_none());
+        let diagnostic = single(&diagnostics);
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(
+            diagnostic.position,
+            Position::Text {
+                line: 11,
+                column: 4
+            }
+        );
+        assert!(diagnostic.message.contains("unknown glyph 'X'"));
+    }
+
+    #[test]
+    fn unreachable_area_is_a_warning() {
+        let (map, diagnostics) =
+            check_map(&map_file(&["#######", "#S.#..#", "#######"]));
+        assert!(map.is_some());
+        let diagnostic = single(&diagnostics);
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.position,
+            Position::Text {
+                line: 11,
+                column: 5
+            }
+        );
+        assert!(diagnostic.message.contains("area of 2 walkable tile(s)"));
+    }
+
+    #[test]
+    fn second_player_start_is_a_warning() {
+        let (map, diagnostics) =
+            check_map(&map_file(&["#####", "#S.S#", "#####"]));
+        assert!(map.is_some());
+        let diagnostic = single(&diagnostics);
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.position,
+            Position::Text {
+                line: 11,
+                column: 4
+            }
+        );
+        assert!(diagnostic.message.contains("player start is placed more"));
+    }
+
+    #[test]
+    fn map_without_start_is_an_error() {
+        let (map, diagnostics) = check_map(&map_file(&["###", "#.#", "###"]));
+        assert!(map.is_none());
+        assert_eq!(single(&diagnostics).severity, Severity::Error);
+    }
+
+    #[test]
+    fn maps_without_text_are_positioned_by_tile() {
+        let mut map =
+            MapData::parse(&map_file(&["#####", "#S.X#", "#####"])).unwrap();
+        map.layout_line = None;
+        let (_, diagnostics) = check_map_data(map);
+        assert_eq!(
+            single(&diagnostics).position,
+            Position::Tile { x: 3, y: 1 }
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use std::f32::consts::PI;
+
+use bevy::{prelude::*, render::camera::Camera2d};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ascii::{spawn_ascii_sprite, AsciiSheet},
+    map::MapData,
+    player::Player,
+    tilemap::{CurrentMap, TileGrid},
+    GameState,
+};
+
+/// Sight radius in tiles while it is foggy.
+const FOG_SIGHT_RADIUS: i32 = 3;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Snow,
+    Fog,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather::Clear
+    }
+}
+
+/// Weather where the player stands, from their tile or else the map.
+#[derive(Default)]
+pub struct CurrentWeather(pub Weather);
+
+#[derive(Component)]
+pub struct WeatherParticle {
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    /// Alpha halfway through the lifetime, particles fade in and out.
+    alpha: f32,
+}
+
+/// How the particles of a weather look and move.
+struct ParticleStyle {
+    glyphs: &'static [usize],
+    color: Color,
+    /// World units per second.
+    velocity: Vec2,
+    /// Random extra velocity, up to this much either way on each axis.
+    jitter: Vec2,
+    lifetime: (f32, f32),
+    scale: f32,
+    /// Particles alive at once.
+    count: usize,
+}
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CurrentWeather>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Overworld)
+                    .with_system(update_weather)
+                    .with_system(spawn_particles.after(update_weather))
+                    .with_system(move_particles),
+            )
+            .add_system_set(
+                SystemSet::on_pause(GameState::Overworld)
+                    .with_system(despawn_particles),
+            );
+    }
+}
+
+impl Weather {
+    /// How far the player sees, `None` when the weather doesn't limit it.
+    pub fn sight_radius(self) -> Option<i32> {
+        match self {
+            Weather::Fog => Some(FOG_SIGHT_RADIUS),
+            _ => None,
+        }
+    }
+
+    fn particles(self) -> Option<ParticleStyle> {
+        match self {
+            Weather::Clear => None,
+            Weather::Rain => Some(ParticleStyle {
+                glyphs: &['/' as usize, ',' as usize],
+                color: Color::rgba(0.5, 0.6, 1.0, 0.7),
+                velocity: Vec2::new(-0.4, -1.6),
+                jitter: Vec2::new(0.05, 0.2),
+                lifetime: (0.3, 0.7),
+                scale: 1.0,
+                count: 120,
+            }),
+            Weather::Snow => Some(ParticleStyle {
+                glyphs: &['*' as usize, 250],
+                color: Color::rgba(1.0, 1.0, 1.0, 0.9),
+                velocity: Vec2::new(0.0, -0.2),
+                jitter: Vec2::new(0.1, 0.05),
+                lifetime: (2.0, 4.0),
+                scale: 0.8,
+                count: 90,
+            }),
+            Weather::Fog => Some(ParticleStyle {
+                glyphs: &[176, 177],
+                color: Color::rgba(0.8, 0.8, 0.85, 0.25),
+                velocity: Vec2::new(0.05, 0.0),
+                jitter: Vec2::new(0.03, 0.01),
+                lifetime: (4.0, 8.0),
+                scale: 4.0,
+                count: 40,
+            }),
+        }
+    }
+}
+
+fn update_weather(
+    mut weather: ResMut<CurrentWeather>,
+    player_query: Query<&Transform, With<Player>>,
+    grid: Res<TileGrid>,
+    current: Option<Res<CurrentMap>>,
+    maps: Res<Assets<MapData>>,
+) {
+    let map_weather = current
+        .and_then(|current| maps.get(&current.handle))
+        .and_then(|map| map.header.weather);
+    let here = grid
+        .tile_at(player_query.single().translation)
+        .and_then(|tile| tile.weather)
+        .or(map_weather)
+        .unwrap_or_default();
+    // Systems react to the weather changing, so only set it when it does
+    if weather.0 != here {
+        weather.0 = here;
+    }
+}
+
+/// Keeps the particles of the current weather spawned across the camera
+/// view, replacing them as fast as they fade out.
+fn spawn_particles(
+    mut commands: Commands,
+    particle_query: Query<&WeatherParticle>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<Camera2d>>,
+    weather: Res<CurrentWeather>,
+    ascii: Res<AsciiSheet>,
+    time: Res<Time>,
+    mut pending: Local<f32>,
+) {
+    let style = match weather.0.particles() {
+        Some(style) => style,
+        None => return,
+    };
+    let (camera, projection) = camera_query.single();
+    let half = Vec2::new(
+        projection.right - projection.left,
+        projection.top - projection.bottom,
+    ) * projection.scale
+        / 2.0;
+
+    let average_lifetime = (style.lifetime.0 + style.lifetime.1) / 2.0;
+    *pending += style.count as f32 / average_lifetime * time.delta_seconds();
+    let missing = style.count.saturating_sub(particle_query.iter().count());
+    let spawned = (*pending as usize).min(missing);
+    *pending -= *pending as usize as f32;
+
+    // Spawned transparent, moving them fades them in
+    let mut color = style.color;
+    color.set_a(0.0);
+    let mut rng = rand::thread_rng();
+    for _ in 0..spawned {
+        let offset = Vec2::new(
+            rng.gen_range(-half.x..=half.x),
+            rng.gen_range(-half.y..=half.y),
+        );
+        let jitter = Vec2::new(
+            rng.gen_range(-style.jitter.x..=style.jitter.x),
+            rng.gen_range(-style.jitter.y..=style.jitter.y),
+        );
+        let glyph = style.glyphs[rng.gen_range(0..style.glyphs.len())];
+        let particle = spawn_ascii_sprite(
+            &mut commands,
+            &ascii,
+            glyph,
+            color,
+            (camera.translation.truncate() + offset).extend(850.0),
+            Vec3::splat(style.scale),
+        );
+        commands
+            .entity(particle)
+            .insert(Name::new("Weather Particle"))
+            .insert(WeatherParticle {
+                velocity: style.velocity + jitter,
+                age: 0.0,
+                lifetime: rng.gen_range(style.lifetime.0..=style.lifetime.1),
+                alpha: style.color.a(),
+            });
+    }
+}
+
+fn move_particles(
+    mut commands: Commands,
+    mut particle_query: Query<(
+        Entity,
+        &mut WeatherParticle,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+    )>,
+    time: Res<Time>,
+) {
+    for (entity, mut particle, mut transform, mut sprite) in
+        particle_query.iter_mut()
+    {
+        particle.age += time.delta_seconds();
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation +=
+            (particle.velocity * time.delta_seconds()).extend(0.0);
+        let fade = (PI * particle.age / particle.lifetime).sin();
+        sprite.color.set_a(particle.alpha * fade);
+    }
+}
+
+fn despawn_particles(
+    mut commands: Commands,
+    particle_query: Query<Entity, With<WeatherParticle>>,
+) {
+    for particle in particle_query.iter() {
+        commands.entity(particle).despawn();
+    }
+}